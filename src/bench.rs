@@ -0,0 +1,148 @@
+use std::time::{Instant, Duration};
+
+use cgmath::Vector2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use wgpu::util::DeviceExt;
+
+use crate::{physics::{Physics, Ball, Boundary}, util::{Vec2, Color}, instance::{Instance, InstanceRaw}, gpu_buffer::GrowableBuffer, render_state};
+
+const SEED: u64 = 0xba11;
+
+/// Entry point for `wgpu_balls bench <name> [args]`.
+pub fn run(args: &[String]) {
+    match args.first().map(String::as_str) {
+        Some("morton") => {
+            let count = args.get(1).and_then(|n| n.parse().ok()).unwrap_or(20_000);
+            morton(count);
+        }
+        Some("instances") => {
+            let count = args.get(1).and_then(|n| n.parse().ok()).unwrap_or(50_000);
+            pollster::block_on(instance_upload(count));
+        }
+        _ => eprintln!("Available benchmarks: morton [ball count], instances [ball count]"),
+    }
+}
+
+/// Physics in random memory order compared to periodically Z-order sorted.
+fn morton(count: usize) {
+    const WARMUP_FRAMES: u32 = 60;
+    const FRAMES: u32 = 300;
+    const REORDER_INTERVAL: u32 = 30;
+
+    println!("Morton reordering, {} balls, {} frames", count, FRAMES);
+
+    for reorder in [false, true] {
+        let mut physics = scattered_pile(count);
+        for _ in 0..WARMUP_FRAMES {
+            physics.update();
+        }
+
+        let mut total = Duration::ZERO;
+        for frame in 0..FRAMES {
+            let start = Instant::now();
+            if reorder && frame % REORDER_INTERVAL == 0 {
+                physics.sort_spatially();
+            }
+            physics.update();
+            total += Instant::now() - start;
+        }
+
+        println!(
+            "  {:<10} {:.3}ms/frame",
+            if reorder { "sorted" } else { "unsorted" },
+            total.as_secs_f64() * 1000.0 / FRAMES as f64,
+        );
+    }
+}
+
+/// Recreating the instance buffer every frame compared to writing into a persistent one.
+async fn instance_upload(count: usize) {
+    const FRAMES: u32 = 300;
+
+    let Some((device, queue)) = headless_device().await else {
+        eprintln!("No graphics adapter available");
+        return;
+    };
+
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let instances = (0..count)
+        .map(|_| Instance {
+            position: Vector2::new(rng.gen::<f32>() * 1920.0, rng.gen::<f32>() * 1080.0),
+            rotation: 0.0,
+            scale: 10.0,
+            color: Color::random(),
+            alpha: 1.0,
+            layer: 0.0,
+            sprite: None,
+            occlusion: 0.0,
+        })
+        .collect::<Vec<_>>();
+
+    println!("Instance upload, {} balls, {} frames", count, FRAMES);
+
+    let mut persistent = GrowableBuffer::new(&device, "Instance Buffer", wgpu::BufferUsages::VERTEX);
+    persistent.reserve(&device, (count * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress);
+
+    for recreate in [true, false] {
+        let mut total = Duration::ZERO;
+
+        for _ in 0..FRAMES {
+            let start = Instant::now();
+            let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+
+            if recreate {
+                let _buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Instance Buffer"),
+                    contents: bytemuck::cast_slice(&instance_data),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+            } else {
+                persistent.write(&queue, 0, bytemuck::cast_slice(&instance_data));
+            }
+
+            queue.submit(None);
+            device.poll(wgpu::Maintain::Wait);
+            total += Instant::now() - start;
+        }
+
+        println!(
+            "  {:<10} {:.3}ms/frame",
+            if recreate { "recreate" } else { "persistent" },
+            total.as_secs_f64() * 1000.0 / FRAMES as f64,
+        );
+    }
+}
+
+async fn headless_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = render_state::request_adapter(&instance, None).await?;
+    Some(render_state::request_device(&adapter).await)
+}
+
+/// A boundary densely filled with balls.
+fn scattered_pile(count: usize) -> Physics {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut physics = Physics::default();
+
+    let Boundary::Circle { center, radius } = physics.boundary else {
+        panic!("Benchmarks expect a circular boundary");
+    };
+
+    // Fill roughly half the area
+    let ball_radius = (radius * radius * 0.5 / count as f32).sqrt();
+
+    // Random positions means neighbours end up scattered across the array
+    let balls = (0..count)
+        .map(|_| {
+            let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+            let dist = rng.gen::<f32>().sqrt() * (radius - ball_radius);
+            let pos = center + Vec2::new(angle.cos(), angle.sin()) * dist;
+            Ball::new(pos.x, pos.y, ball_radius)
+        })
+        .collect::<Vec<_>>();
+
+    for ball in balls.into_iter() {
+        physics.add_ball(ball);
+    }
+    physics
+}
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use crate::quadtree::QuadTreeStats;
+
+/// What the broad phase did over one frame.
+#[derive(Debug, Clone, Default)]
+pub struct BroadPhaseStats {
+    /// Shape of the last tree built this frame.
+    pub tree: QuadTreeStats,
+    /// Pairs handed to the narrow phase, summed over every pass this frame.
+    pub candidate_pairs: usize,
+    /// Candidate pairs that actually overlapped.
+    pub contacts: usize,
+    /// Time spent building trees and resolving collisions.
+    pub time: Duration,
+    pub max_depth: usize,
+    pub max_size: usize,
+}
+
+impl BroadPhaseStats {
+    /// Share of candidate pairs that turned out not to be touching.
+    pub fn false_positive_ratio(&self) -> f32 {
+        if self.candidate_pairs == 0 { return 0.0 }
+        1.0 - self.contacts as f32 / self.candidate_pairs as f32
+    }
+
+    /// One line about the quadtree for the HUD.
+    pub fn summary(&self) -> String {
+        format!(
+            "Tree {} nodes, depth/size {}/{}, false pairs {:.0}%",
+            self.tree.node_count,
+            self.max_depth,
+            self.max_size,
+            self.false_positive_ratio() * 100.0,
+        )
+    }
+
+    /// Leaf counts per depth for the HUD, starting at the shallowest depth that has any.
+    pub fn leaf_depths(&self) -> String {
+        let histogram = &self.tree.depth_histogram;
+        let first = histogram.iter().position(|&count| count > 0).unwrap_or(0);
+        let counts = histogram[first..].iter().map(|count| count.to_string()).collect::<Vec<_>>();
+        format!("Leaves from depth {}: {}", first, counts.join("/"))
+    }
+}
+
+const MIN_DEPTH: usize = 1;
+const MAX_DEPTH: usize = 16;
+const MIN_SIZE: usize = 1;
+const MAX_SIZE: usize = 64;
+
+/// Candidate needs to be this much faster than the current parameters to replace them.
+const IMPROVEMENT_THRESHOLD: f32 = 0.97;
+
+/// Hill-climbs the quadtree parameters towards whatever makes the broad phase fastest.
+///
+/// Alternates between re-measuring the current parameters and trying out a neighbour, so
+/// changing ball counts don't skew the comparison.
+#[derive(Debug, Clone)]
+pub struct AutoTuner {
+    sample_frames: u32,
+    frames: u32,
+    /// Accumulated seconds per ball over the current sample.
+    cost: f32,
+    baseline: (usize, usize),
+    baseline_cost: Option<f32>,
+    trial: Option<(usize, usize)>,
+    next_candidate: usize,
+}
+
+impl AutoTuner {
+    pub fn new(sample_frames: u32) -> Self {
+        Self {
+            sample_frames: sample_frames.max(1),
+            frames: 0,
+            cost: 0.0,
+            baseline: (0, 0),
+            baseline_cost: None,
+            trial: None,
+            next_candidate: 0,
+        }
+    }
+
+    /// Feeds in one frame's statistics and returns the parameters to use for the next frame.
+    pub fn update(&mut self, stats: &BroadPhaseStats, ball_count: usize, params: (usize, usize)) -> (usize, usize) {
+        if ball_count == 0 { return params }
+
+        self.cost += stats.time.as_secs_f32() / ball_count as f32;
+        self.frames += 1;
+        if self.frames < self.sample_frames { return params }
+
+        let cost = self.cost / self.frames as f32;
+        self.cost = 0.0;
+        self.frames = 0;
+
+        match self.trial.take() {
+            // Just measured a candidate, keep it if it's better than the baseline
+            Some(trial) => {
+                let better = self.baseline_cost.is_some_and(|base| cost < base * IMPROVEMENT_THRESHOLD);
+                self.baseline_cost = None;
+
+                if better {
+                    self.next_candidate = 0;
+                    trial
+                } else {
+                    self.next_candidate += 1;
+                    self.baseline
+                }
+            }
+            // Just measured the baseline, try the next neighbour
+            None => {
+                self.baseline = params;
+                self.baseline_cost = Some(cost);
+                let candidates = Self::neighbours(params);
+                let trial = candidates[self.next_candidate % candidates.len()];
+                self.trial = Some(trial);
+                trial
+            }
+        }
+    }
+
+    fn neighbours((depth, size): (usize, usize)) -> Vec<(usize, usize)> {
+        let candidates = [
+            (depth + 1, size),
+            (depth.saturating_sub(1), size),
+            (depth, size + 1),
+            (depth, size.saturating_sub(1)),
+            (depth, size * 2),
+            (depth, size / 2),
+        ];
+
+        let mut valid = candidates.into_iter()
+            .map(|(d, s)| (d.clamp(MIN_DEPTH, MAX_DEPTH), s.clamp(MIN_SIZE, MAX_SIZE)))
+            .filter(|&candidate| candidate != (depth, size))
+            .collect::<Vec<_>>();
+        // Clamping can turn different candidates into the same one anywhere in the list
+        valid.sort_unstable();
+        valid.dedup();
+
+        if valid.is_empty() { vec![(depth, size)] } else { valid }
+    }
+}
+
+impl Default for AutoTuner {
+    fn default() -> Self {
+        Self::new(30)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbours_are_unique_and_in_range() {
+        for params in [(4, 2), (4, 1), (MAX_DEPTH, MAX_SIZE), (MIN_DEPTH, MIN_SIZE), (8, 40)] {
+            let neighbours = AutoTuner::neighbours(params);
+            let mut unique = neighbours.clone();
+            unique.dedup();
+            assert_eq!(neighbours, unique, "{:?}", params);
+
+            for (depth, size) in neighbours {
+                assert_ne!((depth, size), params);
+                assert!((MIN_DEPTH..=MAX_DEPTH).contains(&depth) && (MIN_SIZE..=MAX_SIZE).contains(&size));
+            }
+        }
+        // Both `size - 1` and `size / 2`
+        assert_eq!(AutoTuner::neighbours((4, 2)), vec![(3, 2), (4, 1), (4, 3), (4, 4), (5, 2)]);
+    }
+
+    #[test]
+    fn leaf_depths_skip_empty_levels() {
+        let mut stats = BroadPhaseStats::default();
+        stats.tree.depth_histogram = vec![0, 0, 3, 12, 1];
+        assert_eq!(stats.leaf_depths(), "Leaves from depth 2: 3/12/1");
+    }
+}
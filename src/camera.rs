@@ -0,0 +1,130 @@
+use crate::{util::Vec2, shape::Aabb, uniform::CameraUniform};
+
+/// Maps world space to the window. World space has y pointing down like the screen.
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    /// World position shown in the middle of the window.
+    pub(crate) center: Vec2,
+    /// Pixels per world unit.
+    pub(crate) zoom: f32,
+    /// In radians.
+    pub(crate) rotation: f32,
+    /// Window size in pixels.
+    pub(crate) viewport: Vec2,
+}
+
+impl Camera {
+    pub const MIN_ZOOM: f32 = 0.01;
+    pub const MAX_ZOOM: f32 = 100.0;
+
+    pub fn new(viewport: Vec2) -> Self {
+        Self { center: Vec2::fill(0.0), zoom: 1.0, rotation: 0.0, viewport }
+    }
+
+    /// Centers on `area` and zooms so all of it is visible, with a bit of margin.
+    pub fn fit(&mut self, area: &Aabb) {
+        self.center = area.center();
+        let zoom = self.viewport / (area.size * 1.05);
+        self.zoom = zoom.x.min(zoom.y).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+    }
+
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        let offset = (screen - self.viewport / 2.0) / self.zoom;
+        rotate(offset, self.rotation) + self.center
+    }
+
+    pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+        let offset = rotate(world - self.center, -self.rotation) * self.zoom;
+        offset + self.viewport / 2.0
+    }
+
+    /// Moves the camera so the world follows the mouse being dragged by `delta` pixels.
+    pub fn pan_by_screen(&mut self, delta: Vec2) {
+        self.center -= rotate(delta / self.zoom, self.rotation);
+    }
+
+    /// Zooms by `factor` while keeping the world point under `screen` in place.
+    pub fn zoom_at(&mut self, screen: Vec2, factor: f32) {
+        let anchor = self.screen_to_world(screen);
+        self.zoom = (self.zoom * factor).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.center += anchor - self.screen_to_world(screen);
+    }
+
+    pub fn to_uniform(&self) -> CameraUniform {
+        CameraUniform::new(self.center, self.zoom, self.rotation, self.viewport)
+    }
+}
+
+fn rotate(v: Vec2, angle: f32) -> Vec2 {
+    let (sin, cos) = angle.sin_cos();
+    Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(&b) < 1e-3
+    }
+
+    fn turned_camera() -> Camera {
+        Camera { center: Vec2::new(-40.0, 250.0), zoom: 2.5, rotation: 0.7, viewport: Vec2::new(800.0, 600.0) }
+    }
+
+    #[test]
+    fn screen_and_world_round_trip() {
+        let camera = turned_camera();
+        for point in [Vec2::new(0.0, 0.0), Vec2::new(400.0, 300.0), Vec2::new(799.0, 13.0), Vec2::new(-50.0, 900.0)] {
+            assert!(close(camera.world_to_screen(camera.screen_to_world(point)), point));
+            assert!(close(camera.screen_to_world(camera.world_to_screen(point)), point));
+        }
+    }
+
+    #[test]
+    fn center_of_the_window_is_the_camera_center() {
+        let camera = turned_camera();
+        assert!(close(camera.screen_to_world(Vec2::new(400.0, 300.0)), camera.center));
+
+        let camera = Camera { rotation: 0.0, ..camera };
+        // Half the window width in pixels, at 2.5 pixels per unit
+        assert!(close(camera.screen_to_world(Vec2::new(800.0, 300.0)), Vec2::new(120.0, 250.0)));
+    }
+
+    #[test]
+    fn zoom_keeps_the_point_under_the_mouse() {
+        let mut camera = turned_camera();
+        let mouse = Vec2::new(120.0, 510.0);
+        let before = camera.screen_to_world(mouse);
+
+        camera.zoom_at(mouse, 3.0);
+        assert!((camera.zoom - 7.5).abs() < 1e-5);
+        assert!(close(camera.screen_to_world(mouse), before));
+
+        camera.zoom_at(mouse, 1e6);
+        assert_eq!(camera.zoom, Camera::MAX_ZOOM);
+        assert!(close(camera.screen_to_world(mouse), before));
+    }
+
+    #[test]
+    fn pan_drags_the_world_with_the_mouse() {
+        let mut camera = turned_camera();
+        let grabbed = camera.screen_to_world(Vec2::new(300.0, 200.0));
+
+        camera.pan_by_screen(Vec2::new(35.0, -20.0));
+        assert!(close(camera.screen_to_world(Vec2::new(335.0, 180.0)), grabbed));
+    }
+
+    #[test]
+    fn fit_shows_the_whole_area() {
+        let mut camera = Camera::new(Vec2::new(800.0, 600.0));
+        let area = Aabb::new(Vec2::new(100.0, -200.0), Vec2::new(400.0, 100.0));
+        camera.fit(&area);
+
+        assert!(close(camera.center, area.center()));
+        for corner in [area.pos, area.pos + area.size] {
+            let screen = camera.world_to_screen(corner);
+            assert!((0.0..=800.0).contains(&screen.x) && (0.0..=600.0).contains(&screen.y));
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::{fs::File, io::BufWriter, num::NonZeroU32, path::Path};
+
+/// A frame read back from the GPU.
+pub struct FrameImage {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// Tightly packed RGBA, 8 bits per channel, top row first.
+    pub(crate) rgba: Vec<u8>,
+}
+
+impl FrameImage {
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), png::EncodingError> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgba)
+    }
+}
+
+/// Copies a 4 bytes per pixel texture to the CPU, blocking until it's done.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> FrameImage {
+    let unpadded_bytes_per_row = width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.expect("Failed to map readback buffer"));
+    device.poll(wgpu::Maintain::Wait);
+
+    let mut rgba = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+        let data = slice.get_mapped_range();
+        for row in data.chunks(padded_bytes_per_row as usize) {
+            rgba.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    buffer.unmap();
+
+    let bgra = matches!(format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb);
+    if bgra {
+        for pixel in rgba.chunks_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+
+    FrameImage { width, height, rgba }
+}
@@ -0,0 +1,164 @@
+use crate::{colormap::Colormap, physics::Physics, util::Color};
+
+/// What decides a ball's color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// The color it was spawned with.
+    #[default]
+    Spawn,
+    Speed,
+    KineticEnergy,
+    /// Number of balls it's touching.
+    Contacts,
+    /// How much it's overlapping its neighbours, which shows where a pile is under stress.
+    Pressure,
+    Age,
+    SpawnOrder,
+    Size,
+}
+
+/// The values mapped onto the colormap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorRange {
+    /// Follows the smallest and largest value each frame.
+    Auto,
+    Manual { min: f32, max: f32 },
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 8] = [
+        ColorMode::Spawn,
+        ColorMode::Speed,
+        ColorMode::KineticEnergy,
+        ColorMode::Contacts,
+        ColorMode::Pressure,
+        ColorMode::Age,
+        ColorMode::SpawnOrder,
+        ColorMode::Size,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorMode::Spawn => "spawn",
+            ColorMode::Speed => "speed",
+            ColorMode::KineticEnergy => "energy",
+            ColorMode::Contacts => "contacts",
+            ColorMode::Pressure => "pressure",
+            ColorMode::Age => "age",
+            ColorMode::SpawnOrder => "order",
+            ColorMode::Size => "size",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|&mode| mode == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// The value of the ball at `i`, or `None` for [`ColorMode::Spawn`].
+    fn value(self, physics: &Physics, i: usize) -> Option<f32> {
+        let ball = &physics.balls[i];
+        let value = match self {
+            ColorMode::Spawn => return None,
+            ColorMode::Speed => ball.vel.length(),
+            // Mass goes with area
+            ColorMode::KineticEnergy => 0.5 * ball.radius * ball.radius * ball.vel.dot(&ball.vel),
+            ColorMode::Contacts => ball.contacts as f32,
+            ColorMode::Pressure => ball.pressure,
+            ColorMode::Age => ball.age as f32,
+            ColorMode::SpawnOrder => physics.handles[i].0 as f32,
+            ColorMode::Size => ball.radius,
+        };
+        Some(value)
+    }
+}
+
+/// Recomputes ball colors every frame from a [`ColorMode`] and [`Colormap`].
+#[derive(Debug, Clone)]
+pub struct Coloring {
+    pub(crate) mode: ColorMode,
+    pub(crate) colormap: Colormap,
+    pub(crate) range: ColorRange,
+    /// Range used last frame, smoothed so auto ranges don't flicker.
+    current_range: Option<(f32, f32)>,
+}
+
+impl Default for Coloring {
+    fn default() -> Self {
+        Self {
+            mode: ColorMode::default(),
+            colormap: Colormap::default(),
+            range: ColorRange::Auto,
+            current_range: None,
+        }
+    }
+}
+
+impl Coloring {
+    /// How much of the way to the new auto range is covered each frame.
+    const RANGE_SMOOTHING: f32 = 0.1;
+
+    pub fn set_mode(&mut self, mode: ColorMode) {
+        self.mode = mode;
+        self.current_range = None;
+        if let ColorRange::Manual { .. } = self.range {
+            self.range = ColorRange::Auto;
+        }
+    }
+
+    /// The range colors were last mapped from.
+    pub fn current_range(&self) -> Option<(f32, f32)> {
+        self.current_range
+    }
+
+    /// Switches to a manual range fixed at whatever the auto range currently is, or back to auto.
+    pub fn toggle_locked_range(&mut self) {
+        self.range = match (self.range, self.current_range) {
+            (ColorRange::Auto, Some((min, max))) => ColorRange::Manual { min, max },
+            _ => ColorRange::Auto,
+        };
+    }
+
+    /// Writes the color of every ball into `colors`, taking it from `spawn_colors` in
+    /// [`ColorMode::Spawn`].
+    pub fn apply(&mut self, physics: &Physics, spawn_colors: &[Color], colors: &mut [Color]) {
+        if self.mode == ColorMode::Spawn {
+            colors.copy_from_slice(spawn_colors);
+            self.current_range = None;
+            return;
+        }
+
+        let values = (0..physics.balls.len())
+            .filter_map(|i| self.mode.value(physics, i))
+            .collect::<Vec<_>>();
+
+        let (min, max) = match self.range {
+            ColorRange::Manual { min, max } => (min, max),
+            ColorRange::Auto => {
+                let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+                let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                if values.is_empty() { (0.0, 1.0) } else { self.smooth_range(min, max) }
+            }
+        };
+        self.current_range = Some((min, max));
+
+        let span = (max - min).max(f32::EPSILON);
+        for (color, value) in colors.iter_mut().zip(values) {
+            *color = self.colormap.sample((value - min) / span);
+        }
+    }
+
+    fn smooth_range(&self, min: f32, max: f32) -> (f32, f32) {
+        match self.current_range {
+            Some((old_min, old_max)) => (
+                old_min + (min - old_min) * Self::RANGE_SMOOTHING,
+                old_max + (max - old_max) * Self::RANGE_SMOOTHING,
+            ),
+            None => (min, max),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::util::Color;
+
+/// Maps values in `0..=1` to colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Magma,
+    Turbo,
+    Grayscale,
+}
+
+/// Evenly spaced samples of the matplotlib colormaps, interpolated linearly.
+const VIRIDIS: [[f32; 3]; 9] = [
+    [0.267, 0.005, 0.329],
+    [0.278, 0.176, 0.482],
+    [0.231, 0.322, 0.545],
+    [0.173, 0.447, 0.557],
+    [0.129, 0.569, 0.549],
+    [0.157, 0.682, 0.502],
+    [0.369, 0.788, 0.384],
+    [0.678, 0.863, 0.188],
+    [0.993, 0.906, 0.144],
+];
+
+const MAGMA: [[f32; 3]; 9] = [
+    [0.001, 0.000, 0.014],
+    [0.113, 0.065, 0.277],
+    [0.317, 0.071, 0.485],
+    [0.513, 0.148, 0.508],
+    [0.716, 0.215, 0.475],
+    [0.904, 0.311, 0.392],
+    [0.987, 0.535, 0.382],
+    [0.997, 0.766, 0.528],
+    [0.987, 0.991, 0.750],
+];
+
+impl Colormap {
+    pub const ALL: [Colormap; 4] = [Colormap::Viridis, Colormap::Magma, Colormap::Turbo, Colormap::Grayscale];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Colormap::Viridis => "viridis",
+            Colormap::Magma => "magma",
+            Colormap::Turbo => "turbo",
+            Colormap::Grayscale => "grayscale",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|colormap| colormap.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|&colormap| colormap == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// `t` is clamped to `0..=1`.
+    pub fn sample(self, t: f32) -> Color {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+
+        match self {
+            Colormap::Viridis => interpolate(&VIRIDIS, t),
+            Colormap::Magma => interpolate(&MAGMA, t),
+            Colormap::Turbo => turbo(t),
+            Colormap::Grayscale => Color::new(t, t, t),
+        }
+    }
+}
+
+fn interpolate(stops: &[[f32; 3]], t: f32) -> Color {
+    let x = t * (stops.len() - 1) as f32;
+    let i = (x as usize).min(stops.len() - 2);
+    let f = x - i as f32;
+
+    let [r, g, b] = [0, 1, 2].map(|c| stops[i][c] + (stops[i + 1][c] - stops[i][c]) * f);
+    Color::new(r, g, b)
+}
+
+/// Polynomial fit of Google's Turbo colormap.
+fn turbo(t: f32) -> Color {
+    let r = 0.13572138 + t * (4.6153926 + t * (-42.660323 + t * (132.13108 + t * (-152.9424 + t * 59.28638))));
+    let g = 0.09140261 + t * (2.1941884 + t * (4.8429666 + t * (-14.185033 + t * (4.2772986 + t * 2.829566))));
+    let b = 0.1066733 + t * (12.641946 + t * (-60.582047 + t * (110.36277 + t * (-89.90311 + t * 27.34825))));
+
+    Color::new(r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0))
+}
@@ -0,0 +1,102 @@
+use crate::{vertex::Vertex, util::Vec2, shape::Aabb, physics::{Physics, Boundary, Obstacle}};
+
+/// Which debug overlays are drawn on top of the balls.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DebugOverlays {
+    pub(crate) quadtree: bool,
+    pub(crate) velocities: bool,
+    pub(crate) contacts: bool,
+    pub(crate) aabbs: bool,
+    pub(crate) boundary: bool,
+}
+
+impl DebugOverlays {
+    pub fn any(&self) -> bool {
+        self.quadtree || self.velocities || self.contacts || self.aabbs || self.boundary
+    }
+}
+
+/// Collects world space lines to be drawn as a line list.
+#[derive(Debug, Clone, Default)]
+pub struct DebugDraw {
+    pub(crate) vertices: Vec<Vertex>,
+}
+
+impl DebugDraw {
+    /// How many frames ahead velocity vectors reach.
+    const VELOCITY_SCALE: f32 = 10.0;
+    /// Contact normals are this long per unit of penetration, so deeper contacts stand out.
+    const DEPTH_SCALE: f32 = 10.0;
+    const CIRCLE_SEGMENTS: u32 = 128;
+
+    pub fn line(&mut self, start: Vec2, end: Vec2, color: [f32; 3]) {
+        self.vertices.push(Vertex { position: [start.x, start.y], color });
+        self.vertices.push(Vertex { position: [end.x, end.y], color });
+    }
+
+    pub fn rect(&mut self, aabb: &Aabb, color: [f32; 3]) {
+        let min = aabb.pos;
+        let max = aabb.max();
+        let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+
+        for i in 0..4 {
+            self.line(corners[i], corners[(i + 1) % 4], color);
+        }
+    }
+
+    pub fn circle(&mut self, center: Vec2, radius: f32, color: [f32; 3]) {
+        let point = |i: u32| {
+            let angle = i as f32 / Self::CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + Vec2::new(angle.cos(), angle.sin()) * radius
+        };
+
+        for i in 0..Self::CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Adds the enabled overlays for the current state of `physics`.
+    pub fn physics(&mut self, physics: &Physics, overlays: &DebugOverlays) {
+        if overlays.quadtree {
+            physics.with_ball_tree(|tree| {
+                for (bounds, depth) in tree.map(|tree| tree.node_bounds()).unwrap_or_default() {
+                    // Deeper nodes get brighter
+                    let shade = 0.2 + 0.8 * (depth as f32 / physics.quadtree_max_depth.max(1) as f32);
+                    self.rect(&bounds, [0.0, shade, 0.3 * shade]);
+                }
+            });
+        }
+
+        if overlays.aabbs {
+            for ball in physics.balls.iter() {
+                self.rect(&ball.aabb(), [0.3, 0.5, 1.0]);
+            }
+        }
+
+        if overlays.velocities {
+            for ball in physics.balls.iter() {
+                self.line(ball.pos, ball.pos + ball.vel * Self::VELOCITY_SCALE, [1.0, 1.0, 0.0]);
+            }
+        }
+
+        if overlays.contacts {
+            for contact in physics.contacts.iter().flatten() {
+                let end = contact.point + contact.normal * (contact.depth * Self::DEPTH_SCALE);
+                self.line(contact.point, end, [1.0, 0.1, 0.1]);
+            }
+        }
+
+        if overlays.boundary {
+            if let Boundary::Circle { center, radius } = physics.boundary {
+                self.circle(center, radius, [1.0, 1.0, 1.0]);
+            }
+
+            for obstacle in physics.obstacles.iter() {
+                match *obstacle {
+                    Obstacle::Circle { center, radius } => self.circle(center, radius, [1.0, 1.0, 1.0]),
+                    Obstacle::Segment { start, end } => self.line(start, end, [1.0, 1.0, 1.0]),
+                }
+            }
+        }
+    }
+}
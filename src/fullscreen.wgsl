@@ -0,0 +1,119 @@
+// Passes that cover the whole target with one triangle
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Texture coordinates, (0, 0) at the top left
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+    // (-1, -1), (3, -1), (-1, 3)
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: FullscreenOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+// Copies `source` pixel for pixel, it must be the same size as the target
+@fragment
+fn fs_blit(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return textureLoad(source, vec2<i32>(in.clip_position.xy), 0);
+}
+
+// Like `fs_blit`, but sRGB encodes the color for targets that don't do it themselves
+@fragment
+fn fs_encode_srgb(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(source, vec2<i32>(in.clip_position.xy), 0);
+    let low = color.rgb * 12.92;
+    let high = 1.055 * pow(color.rgb, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return vec4<f32>(select(high, low, color.rgb <= vec3<f32>(0.0031308)), color.a);
+}
+
+// Used with a reverse subtract blend and the blend constant as the amount to take away
+@fragment
+fn fs_fade(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}
+
+// Used with a max blend to stop float targets from fading below zero
+@fragment
+fn fs_zero(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+}
+
+// Post-processing
+
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+// Meaning depends on the pass
+struct PostParams {
+    values: vec4<f32>,
+}
+@group(0) @binding(2)
+var<uniform> params: PostParams;
+
+// Second input, e.g. the blurred highlights for the bloom composite
+@group(0) @binding(3)
+var overlay: texture_2d<f32>;
+
+// Keeps what's brighter than `params.values.x`, fading in smoothly above it
+@fragment
+fn fs_threshold(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - params.values.x, 0.0) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+// 9 tap Gaussian blur along `params.values.xy`, in texels
+@fragment
+fn fs_blur(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = params.values.xy / vec2<f32>(textureDimensions(source));
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+    var color = textureSample(source, source_sampler, in.uv).rgb * weights[0];
+    for (var i = 1; i < 5; i = i + 1) {
+        let offset = texel * f32(i);
+        color = color + textureSample(source, source_sampler, in.uv + offset).rgb * weights[i];
+        color = color + textureSample(source, source_sampler, in.uv - offset).rgb * weights[i];
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+// Adds `overlay` scaled by `params.values.x`
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv).rgb;
+    let bloom = textureSample(overlay, source_sampler, in.uv).rgb;
+    return vec4<f32>(color + bloom * params.values.x, 1.0);
+}
+
+// ACES filmic curve fit, with `params.values.x` as exposure
+@fragment
+fn fs_tonemap(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let x = textureSample(source, source_sampler, in.uv).rgb * params.values.x;
+    let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+    return vec4<f32>(clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
+
+// Darkens the corners by up to `params.values.x`, starting `params.values.y` from the center
+@fragment
+fn fs_vignette(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv).rgb;
+    // 1 at the corners
+    let distance = length(in.uv - 0.5) * 1.41421356;
+    let darken = smoothstep(params.values.y, 1.0, distance) * params.values.x;
+    return vec4<f32>(color * (1.0 - darken), 1.0);
+}
+
+@fragment
+fn fs_copy(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureSample(source, source_sampler, in.uv).rgb, 1.0);
+}
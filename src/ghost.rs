@@ -0,0 +1,61 @@
+use crate::{physics::Ball, instance::Instance, timestep::Smoothing, util::Color};
+
+/// A ball that's only drawn. It carries on under gravity straight through everything else
+/// while it fades out, like a ball that was just despawned.
+#[derive(Debug, Clone)]
+pub struct Ghost {
+    /// Only moved, never collided. Its age counts towards `lifetime`.
+    pub(crate) ball: Ball,
+    pub(crate) color: Color,
+    pub(crate) sprite: Option<u16>,
+    /// Opacity it starts fading from.
+    pub(crate) alpha: f32,
+    /// Steps it takes to fade out completely.
+    pub(crate) lifetime: u32,
+}
+
+impl Ghost {
+    /// Drawn under the balls when layer ordering is on.
+    pub const LAYER: f32 = -1.0;
+
+    pub fn new(mut ball: Ball, color: Color, alpha: f32, lifetime: u32) -> Self {
+        ball.age = 0;
+        Self { ball, color, sprite: None, alpha, lifetime: lifetime.max(1) }
+    }
+
+    /// Moves on one step, split into `substeps` like in [`crate::physics::Physics::update`]
+    /// so it keeps to the path a ball would take. Returns `false` once it has faded out.
+    pub fn update(&mut self, substeps: u32) -> bool {
+        let ball = &mut self.ball;
+        ball.prev_pos = ball.pos;
+        ball.prev_angle = ball.angle;
+        ball.age += 1;
+
+        let dt = 1.0 / substeps as f32;
+        for _ in 0..substeps {
+            ball.apply(dt);
+            ball.update_pos(dt);
+        }
+
+        ball.age < self.lifetime
+    }
+
+    pub fn current_alpha(&self) -> f32 {
+        self.alpha * (1.0 - self.ball.age as f32 / self.lifetime as f32).max(0.0)
+    }
+
+    /// Placed like a ball, see [`Smoothing::position`].
+    pub fn instance(&self, smoothing: Smoothing, alpha: f32) -> Instance {
+        let ball = &self.ball;
+        Instance {
+            position: smoothing.position(ball.prev_pos, ball.pos, alpha).into(),
+            rotation: smoothing.angle(ball.prev_angle, ball.angle, alpha),
+            scale: ball.radius,
+            color: self.color,
+            alpha: self.current_alpha(),
+            layer: Self::LAYER,
+            sprite: self.sprite,
+            occlusion: 0.0,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/// A GPU buffer that keeps its allocation between frames and doubles in size when it runs out.
+pub struct GrowableBuffer {
+    buffer: wgpu::Buffer,
+    /// In bytes.
+    capacity: wgpu::BufferAddress,
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+}
+
+impl GrowableBuffer {
+    const MIN_CAPACITY: wgpu::BufferAddress = 4096;
+
+    pub fn new(device: &wgpu::Device, label: &'static str, usage: wgpu::BufferUsages) -> Self {
+        let usage = usage | wgpu::BufferUsages::COPY_DST;
+
+        Self {
+            buffer: create_buffer(device, label, usage, Self::MIN_CAPACITY),
+            capacity: Self::MIN_CAPACITY,
+            label,
+            usage,
+        }
+    }
+
+    /// Makes sure `size` bytes fit. Returns `true` if the buffer had to be reallocated, in
+    /// which case its previous contents are gone.
+    pub fn reserve(&mut self, device: &wgpu::Device, size: wgpu::BufferAddress) -> bool {
+        if size <= self.capacity { return false }
+
+        let mut capacity = self.capacity;
+        while capacity < size {
+            capacity *= 2;
+        }
+
+        self.buffer = create_buffer(device, self.label, self.usage, capacity);
+        self.capacity = capacity;
+        true
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, offset: wgpu::BufferAddress, data: &[u8]) {
+        if data.is_empty() { return }
+        queue.write_buffer(&self.buffer, offset, data);
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn capacity(&self) -> wgpu::BufferAddress {
+        self.capacity
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, label: &str, usage: wgpu::BufferUsages, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,230 @@
+use std::path::PathBuf;
+
+use crate::{state::State, spawner::Spawner, util::Vec2, coloring::{ColorMode, ColorRange}, colormap::Colormap, post::PostEffect, render_state::SAMPLE_COUNTS, sprite::SpriteAtlas, image::Image, lighting::Shading, scenery::SceneryStyle};
+
+const SEED: u64 = 0xba11;
+const GHOST_SEED: u64 = 0x6057;
+
+/// Options for `headless`, e.g. `headless --frames 600 --every 10 --out frames`.
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    pub(crate) frames: u32,
+    /// Simulation steps between rendered frames.
+    pub(crate) steps_per_frame: u32,
+    /// Save every this many frames.
+    pub(crate) every: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// A directory for PNGs, or a `.y4m` file.
+    pub(crate) out: PathBuf,
+    pub(crate) color_mode: ColorMode,
+    pub(crate) colormap: Colormap,
+    pub(crate) color_range: ColorRange,
+    /// Frames trails take to fade, if drawn at all.
+    pub(crate) trails: Option<u32>,
+    pub(crate) post_effects: Vec<PostEffect>,
+    pub(crate) shading: Shading,
+    pub(crate) scenery: SceneryStyle,
+    /// Steps before balls are despawned and fade out, if ever.
+    pub(crate) lifetime: Option<u32>,
+    /// Opacity of every ball.
+    pub(crate) opacity: f32,
+    /// Also pour in ghosts that fade out over this many steps.
+    pub(crate) ghosts: Option<u32>,
+    /// Draw in layer order, see [`RenderState::set_layer_order`](crate::render_state::RenderState::set_layer_order).
+    pub(crate) layer_order: bool,
+    /// Draw the HUD into the frames, which makes them differ from run to run.
+    pub(crate) hud: bool,
+    /// Samples per pixel, lowered to what the adapter supports.
+    pub(crate) msaa: u32,
+    /// PNG or directory of PNGs to draw the balls with.
+    pub(crate) sprites: Option<PathBuf>,
+    /// Image for the pile to settle into, see [`State::paint_picture`].
+    pub(crate) picture: Option<PathBuf>,
+    /// Steps to run before taking colors from `picture`. If not set, as many as the whole
+    /// run takes, `frames` times `steps_per_frame`.
+    pub(crate) settle: Option<u32>,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self {
+            frames: 600,
+            steps_per_frame: 1,
+            every: 10,
+            width: 1280,
+            height: 720,
+            out: PathBuf::from("frames"),
+            color_mode: ColorMode::default(),
+            colormap: Colormap::default(),
+            color_range: ColorRange::Auto,
+            trails: None,
+            post_effects: Vec::new(),
+            shading: Shading::Flat,
+            scenery: SceneryStyle::default(),
+            lifetime: None,
+            opacity: 1.0,
+            ghosts: None,
+            layer_order: false,
+            hud: false,
+            msaa: 1,
+            sprites: None,
+            picture: None,
+            settle: None,
+        }
+    }
+}
+
+impl HeadlessOptions {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("Missing value for {}", flag))?;
+            let number = || value.parse::<u32>().map_err(|_| format!("Invalid value for {}: {}", flag, value));
+
+            match flag.as_str() {
+                "--frames" => options.frames = number()?,
+                "--batch" => options.steps_per_frame = number()?.max(1),
+                "--every" => options.every = number()?.max(1),
+                "--width" => options.width = number()?.max(1),
+                "--height" => options.height = number()?.max(1),
+                "--trails" => options.trails = Some(number()?),
+                "--msaa" => options.msaa = match number()? {
+                    count if SAMPLE_COUNTS.contains(&count) => count,
+                    count => return Err(format!("Unsupported MSAA sample count {}, expected one of {:?}", count, SAMPLE_COUNTS)),
+                },
+                "--post" => options.post_effects = match value.as_str() {
+                    "neon" => PostEffect::neon(),
+                    "none" => Vec::new(),
+                    _ => return Err(format!("Unknown post-processing {}, expected neon or none", value)),
+                },
+                "--out" => options.out = PathBuf::from(value),
+                "--shading" => options.shading = Shading::from_name(value).ok_or_else(|| format!("Unknown shading {}, expected flat, lit or lit-ao", value))?,
+                "--scenery-colors" => options.scenery = options.scenery.with_colors(value).ok_or_else(|| format!("Invalid scenery colors {}, expected INTERIOR,OUTLINE,OBSTACLE", value))?,
+                "--lifetime" => options.lifetime = Some(number()?.max(1)),
+                "--opacity" => options.opacity = value.parse::<f32>().ok().filter(|opacity| (0.0..=1.0).contains(opacity)).ok_or_else(|| format!("Invalid opacity {}, expected 0 to 1", value))?,
+                "--ghosts" => options.ghosts = Some(number()?.max(1)),
+                "--layer-order" => options.layer_order = match value.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("Invalid layer ordering {}, expected on or off", value)),
+                },
+                "--hud" => options.hud = match value.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("Invalid HUD setting {}, expected on or off", value)),
+                },
+                "--sprites" => options.sprites = Some(PathBuf::from(value)),
+                "--picture" => options.picture = Some(PathBuf::from(value)),
+                "--settle" => options.settle = Some(number()?),
+                "--color" => options.color_mode = ColorMode::from_name(value).ok_or_else(|| format!("Unknown color mode {}", value))?,
+                "--colormap" => options.colormap = Colormap::from_name(value).ok_or_else(|| format!("Unknown colormap {}", value))?,
+                "--range" => options.color_range = parse_range(value).ok_or_else(|| format!("Invalid range {}, expected MIN,MAX", value))?,
+                _ => return Err(format!("Unknown option {}", flag)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Steps the simulation without a window and saves frames as PNGs.
+pub fn run(args: &[String]) {
+    let options = match HeadlessOptions::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Usage: headless [--frames N] [--batch N] [--every N] [--width N] [--height N] [--out PATH] [--color MODE] [--colormap NAME] [--range MIN,MAX] [--trails FRAMES] [--post neon|none] [--msaa 1|2|4|8] [--shading flat|lit|lit-ao] [--scenery-colors INTERIOR,OUTLINE,OBSTACLE] [--lifetime N] [--opacity X] [--ghosts N] [--layer-order on|off] [--hud on|off] [--sprites PATH] [--picture PATH [--settle STEPS]]");
+            return;
+        }
+    };
+
+    pollster::block_on(render_frames(options));
+}
+
+async fn render_frames(options: HeadlessOptions) {
+    let Some(mut state) = State::new_headless(options.width, options.height).await else {
+        eprintln!("No graphics adapter available");
+        return;
+    };
+
+    if let Some(path) = options.sprites.as_ref() {
+        match SpriteAtlas::load(path) {
+            Ok(atlas) => state.set_sprites(&atlas),
+            Err(e) => {
+                eprintln!("Couldn't load sprites: {}", e);
+                return;
+            }
+        }
+    }
+
+    state.spawners.push(default_spawner());
+    state.ball_lifetime = options.lifetime;
+    state.ball_opacity = options.opacity;
+    if let Some(lifetime) = options.ghosts {
+        state.spawners.push(ghost_spawner(lifetime));
+    }
+    if let Some(path) = options.picture.as_ref() {
+        match Image::load_png(path) {
+            Ok(image) => state.paint_picture(&image, options.settle.unwrap_or(options.frames * options.steps_per_frame)),
+            Err(e) => {
+                eprintln!("Couldn't load picture: {}", e);
+                return;
+            }
+        }
+    }
+
+    state.steps_per_frame = Some(options.steps_per_frame);
+    state.start_recording(&options.out, options.every);
+    if state.recorder.is_none() { return }
+    state.coloring.set_mode(options.color_mode);
+    state.coloring.colormap = options.colormap;
+    state.coloring.range = options.color_range;
+    let msaa = state.render_state.set_sample_count(options.msaa);
+    if msaa != options.msaa {
+        eprintln!("{}x MSAA isn't supported, using {}x", options.msaa, msaa);
+    }
+    state.render_state.set_shading(options.shading);
+    state.scenery_style = options.scenery;
+    state.render_state.set_layer_order(options.layer_order);
+    state.hud.visible = options.hud;
+    state.render_state.set_post_effects(options.post_effects);
+    state.render_state.set_trails(options.trails);
+
+    for frame in 0..options.frames {
+        state.step();
+        if let Err(e) = state.render() {
+            eprintln!("Frame {}: {:?}", frame, e);
+            continue;
+        }
+
+        state.record_frame();
+        if state.recorder.is_none() { return }
+    }
+
+    state.stop_recording();
+}
+
+fn parse_range(value: &str) -> Option<ColorRange> {
+    let (min, max) = value.split_once(',')?;
+    Some(ColorRange::Manual { min: min.trim().parse().ok()?, max: max.trim().parse().ok()? })
+}
+
+/// Pours ghosts that fade out over `lifetime` steps in alongside [`default_spawner`], forever.
+pub fn ghost_spawner(lifetime: u32) -> Spawner {
+    let mut spawner = Spawner::new(Vec2::new(0.0, -400.0), GHOST_SEED);
+    spawner.spread = 150.0;
+    spawner.ghost_lifetime = Some(lifetime);
+    spawner
+}
+
+/// Pours balls in from the top of the default boundary.
+pub fn default_spawner() -> Spawner {
+    let mut spawner = Spawner::new(Vec2::new(0.0, -400.0), SEED);
+    spawner.rate = 2;
+    spawner.spread = 150.0;
+    spawner.max_balls = 2000;
+    spawner
+}
@@ -0,0 +1,188 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+use crate::{vertex::Vertex, util::Vec2, broad_phase::BroadPhaseStats, font::{self, GLYPH_WIDTH, GLYPH_HEIGHT}};
+
+/// Numbers shown on the HUD besides timings.
+#[derive(Debug, Clone, Copy)]
+pub struct HudStats<'a> {
+    pub(crate) balls: usize,
+    pub(crate) contacts: usize,
+    pub(crate) broad_phase: &'a BroadPhaseStats,
+    pub(crate) substeps: u32,
+    pub(crate) iterations: u32,
+    pub(crate) color_mode: &'static str,
+    pub(crate) colormap: &'static str,
+    /// Values at either end of the colormap, if the color mode uses one.
+    pub(crate) color_range: Option<(f32, f32)>,
+}
+
+/// On-screen text with timings and a graph of recent frame times.
+pub struct Hud {
+    pub(crate) visible: bool,
+    /// Screen pixels per font pixel.
+    pub(crate) scale: f32,
+    /// In seconds, oldest first.
+    frame_times: VecDeque<f32>,
+    last_frame: Option<Instant>,
+    /// Smoothed, in seconds.
+    physics_time: f32,
+    render_time: f32,
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            scale: 2.0,
+            frame_times: VecDeque::with_capacity(Self::GRAPH_FRAMES),
+            last_frame: None,
+            physics_time: 0.0,
+            render_time: 0.0,
+        }
+    }
+}
+
+impl Hud {
+    const GRAPH_FRAMES: usize = 120;
+    /// Frame time at the top of the graph, in seconds.
+    const GRAPH_MAX: f32 = 1.0 / 30.0;
+    const GRAPH_HEIGHT: f32 = 20.0;
+    /// How much of each new sample goes into the smoothed timings.
+    const SMOOTHING: f32 = 0.05;
+    const MARGIN: f32 = 4.0;
+
+    const TEXT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+    const PANEL_COLOR: [f32; 3] = [0.05, 0.05, 0.05];
+
+    /// Marks the start of a new frame.
+    pub fn frame(&mut self, now: Instant) {
+        if let Some(last) = self.last_frame {
+            if self.frame_times.len() == Self::GRAPH_FRAMES {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back((now - last).as_secs_f32());
+        }
+        self.last_frame = Some(now);
+    }
+
+    pub fn record_physics(&mut self, time: Duration) {
+        smooth(&mut self.physics_time, time.as_secs_f32());
+    }
+
+    pub fn record_render(&mut self, time: Duration) {
+        smooth(&mut self.render_time, time.as_secs_f32());
+    }
+
+    /// Average over the graph.
+    pub fn frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() { return 0.0 }
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+
+    /// Triangles in screen pixels for the HUD, or nothing if it's hidden.
+    pub fn build(&self, stats: &HudStats) -> Vec<Vertex> {
+        if !self.visible { return Vec::new() }
+
+        let frame_time = self.frame_time();
+        let fps = if frame_time > 0.0 { 1.0 / frame_time } else { 0.0 };
+        let color = match stats.color_range {
+            Some((min, max)) => format!("Color {} {} {:.2} - {:.2}", stats.color_mode, stats.colormap, min, max),
+            None => format!("Color {}", stats.color_mode),
+        };
+        let lines = [
+            format!("FPS {:.0} ({:.1} ms)", fps, frame_time * 1000.0),
+            format!("Physics {:.2} ms", self.physics_time * 1000.0),
+            format!("Render {:.2} ms", self.render_time * 1000.0),
+            format!("Balls {}", stats.balls),
+            format!("Contacts {}", stats.contacts),
+            format!("Substeps {}, iterations {}", stats.substeps, stats.iterations),
+            stats.broad_phase.summary(),
+            stats.broad_phase.leaf_depths(),
+            color,
+        ];
+
+        let pixel = self.scale;
+        let line_height = (GLYPH_HEIGHT + 2) as f32 * pixel;
+        let text_width = lines.iter().map(|line| line.len()).max().unwrap_or(0) as f32 * (GLYPH_WIDTH + 1) as f32 * pixel;
+        let graph_width = Self::GRAPH_FRAMES as f32 * pixel;
+        let graph_height = Self::GRAPH_HEIGHT * pixel;
+
+        let mut draw = HudDraw::default();
+        let origin = Vec2::fill(Self::MARGIN * pixel);
+        let padding = Vec2::fill(2.0 * pixel);
+        let content = Vec2::new(text_width.max(graph_width), lines.len() as f32 * line_height + graph_height);
+        draw.rect(origin - padding, content + padding * 2.0, Self::PANEL_COLOR);
+
+        for (i, line) in lines.iter().enumerate() {
+            draw.text(origin + Vec2::new(0.0, i as f32 * line_height), line, pixel, Self::TEXT_COLOR);
+        }
+
+        let graph_bottom = origin.y + lines.len() as f32 * line_height + graph_height;
+        for (i, &time) in self.frame_times.iter().enumerate() {
+            let height = (time / Self::GRAPH_MAX).min(1.0) * graph_height;
+            let color = if time <= 1.0 / 55.0 {
+                [0.2, 0.9, 0.2]
+            } else if time <= 1.0 / 28.0 {
+                [0.9, 0.9, 0.2]
+            } else {
+                [0.9, 0.2, 0.2]
+            };
+
+            let x = origin.x + i as f32 * pixel;
+            draw.rect(Vec2::new(x, graph_bottom - height), Vec2::new(pixel, height), color);
+        }
+
+        // 60 fps marker
+        let target_y = graph_bottom - (1.0 / 60.0) / Self::GRAPH_MAX * graph_height;
+        draw.rect(Vec2::new(origin.x, target_y), Vec2::new(graph_width, 1.0), [0.5, 0.5, 0.5]);
+
+        draw.vertices
+    }
+}
+
+fn smooth(value: &mut f32, sample: f32) {
+    *value += (sample - *value) * Hud::SMOOTHING;
+}
+
+/// Builds screen space triangles.
+#[derive(Debug, Clone, Default)]
+struct HudDraw {
+    vertices: Vec<Vertex>,
+}
+
+impl HudDraw {
+    fn rect(&mut self, pos: Vec2, size: Vec2, color: [f32; 3]) {
+        let max = pos + size;
+        let corners = [[pos.x, pos.y], [max.x, pos.y], [max.x, max.y], [pos.x, max.y]];
+
+        for i in [0, 1, 2, 0, 2, 3] {
+            self.vertices.push(Vertex { position: corners[i], color });
+        }
+    }
+
+    /// Draws `text` with its top left corner at `pos`, merging runs of lit pixels into one quad.
+    fn text(&mut self, pos: Vec2, text: &str, pixel: f32, color: [f32; 3]) {
+        for (i, c) in text.chars().enumerate() {
+            let glyph_x = pos.x + (i as u32 * (GLYPH_WIDTH + 1)) as f32 * pixel;
+
+            for (row, bits) in font::glyph(c).iter().enumerate() {
+                let y = pos.y + row as f32 * pixel;
+                let mut col = 0;
+                while col < GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                        col += 1;
+                        continue;
+                    }
+
+                    let start = col;
+                    while col < GLYPH_WIDTH && bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0 {
+                        col += 1;
+                    }
+
+                    let x = glyph_x + start as f32 * pixel;
+                    self.rect(Vec2::new(x, y), Vec2::new((col - start) as f32 * pixel, pixel), color);
+                }
+            }
+        }
+    }
+}
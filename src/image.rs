@@ -0,0 +1,56 @@
+use std::{fs::File, io, path::Path};
+
+/// A decoded image.
+pub struct Image {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// RGBA, 8 bits per channel, top row first.
+    pub(crate) rgba: Vec<u8>,
+}
+
+impl Image {
+    /// Bilinearly filtered color at `u`, `v` in `0..1`.
+    pub fn sample(&self, u: f32, v: f32) -> [u8; 4] {
+        let x = (u * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x.fract(), y.fract());
+
+        let pixel = |x: u32, y: u32| {
+            let start = ((y * self.width + x) * 4) as usize;
+            &self.rgba[start..start + 4]
+        };
+        let (p00, p10, p01, p11) = (pixel(x0, y0), pixel(x1, y0), pixel(x0, y1), pixel(x1, y1));
+
+        let mut out = [0; 4];
+        for c in 0..4 {
+            let top = p00[c] as f32 * (1.0 - fx) + p10[c] as f32 * fx;
+            let bottom = p01[c] as f32 * (1.0 - fx) + p11[c] as f32 * fx;
+            out[c] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+        }
+        out
+    }
+
+    /// Decodes a PNG of any color type into 8 bit RGBA.
+    pub fn load_png(path: &Path) -> io::Result<Self> {
+        let invalid = |e: png::DecodingError| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e));
+
+        let mut decoder = png::Decoder::new(File::open(path)?);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(invalid)?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(invalid)?;
+        let pixels = &buf[..info.buffer_size()];
+
+        let rgba = match info.color_type {
+            png::ColorType::Rgba => pixels.to_vec(),
+            png::ColorType::Rgb => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+            png::ColorType::Indexed => unreachable!("expanded by normalize_to_color8"),
+        };
+
+        Ok(Self { width: info.width, height: info.height, rgba })
+    }
+}
@@ -1,12 +1,27 @@
 
-use winit::{event::{KeyboardInput, ElementState}, dpi::PhysicalPosition};
+use winit::{event::{KeyboardInput, ElementState, VirtualKeyCode, MouseScrollDelta}, dpi::PhysicalPosition};
 
 use crate::{util::Vec2};
 
+const PAN_BUTTON: usize = 3;
+
 pub struct InputHandler {
     mouse_pos: Option<PhysicalPosition<f64>>,
     button_states: [bool; 32],
+    /// Where to spawn balls, in screen pixels.
     pub(crate) balls_to_add: Vec<Vec2>,
+    /// How far the view was dragged since the last update, in screen pixels.
+    pub(crate) pan_delta: Vec2,
+    /// Scroll wheel lines since the last update, positive is away from the user.
+    pub(crate) scroll: f32,
+    /// Keys pressed since the last update, in order.
+    pub(crate) keys_pressed: Vec<VirtualKeyCode>,
+}
+
+impl Default for InputHandler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InputHandler {
@@ -15,6 +30,9 @@ impl InputHandler {
             mouse_pos: None,
             button_states: [false; 32],
             balls_to_add: Vec::new(),
+            pan_delta: Vec2::fill(0.0),
+            scroll: 0.0,
+            keys_pressed: Vec::new(),
         }
     }
 
@@ -25,14 +43,37 @@ impl InputHandler {
         }
     }
 
-    pub fn handle_kb_input(&self, _input: &KeyboardInput) {
+    pub fn handle_kb_input(&mut self, input: &KeyboardInput) {
+        if input.state != ElementState::Pressed { return }
 
+        if let Some(key) = input.virtual_keycode {
+            self.keys_pressed.push(key);
+        }
     }
 
     pub fn handle_cursor_movement(&mut self, input: &PhysicalPosition<f64>) {
+        if let Some(old) = self.mouse_pos {
+            if self.button_states[PAN_BUTTON] {
+                self.pan_delta += Vec2::new((input.x - old.x) as f32, (input.y - old.y) as f32);
+            }
+        }
+
         self.mouse_pos = Some(input.to_owned());
     }
 
+    pub fn handle_mouse_wheel(&mut self, delta: &MouseScrollDelta) {
+        self.scroll += match delta {
+            MouseScrollDelta::LineDelta(_, y) => *y,
+            // Roughly one line per 50 pixels
+            MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / 50.0,
+        };
+    }
+
+    /// Last known cursor position in screen pixels.
+    pub fn mouse_pos(&self) -> Option<Vec2> {
+        self.mouse_pos.map(|pos| Vec2::new(pos.x as f32, pos.y as f32))
+    }
+
     pub fn handle_mouse_button(&mut self, button_id: u32, state: ElementState) {
         if button_id >= 32 { panic!("There's no way you have 33 buttons on your mouse wtf") };
         let pressed = state == ElementState::Pressed;
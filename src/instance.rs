@@ -1,37 +1,60 @@
-use cgmath::{Quaternion, Matrix4, Vector2};
+use cgmath::Vector2;
 
-use crate::util::Color;
+use std::f32::consts::{PI, TAU};
 
+use crate::util::{Color, to_f16_bits};
+
+#[derive(Clone, Copy, PartialEq)]
 pub struct Instance {
     pub(crate) position: Vector2<f32>,
-    pub(crate) rotation: Quaternion<f32>,
+    /// In radians.
+    pub(crate) rotation: f32,
     pub(crate) scale: f32,
     pub(crate) color: Color,
+    /// Opacity from `0.0` to `1.0`, uploaded as the alpha of `color`.
+    pub(crate) alpha: f32,
+    /// With layer ordering on, instances in higher layers are drawn over lower ones.
+    pub(crate) layer: f32,
+    /// Cell of the [`SpriteAtlas`](crate::sprite::SpriteAtlas) drawn on the ball.
+    pub(crate) sprite: Option<u16>,
+    /// How crowded the ball is from `0.0` to `1.0`, for ambient occlusion.
+    pub(crate) occlusion: f32,
 }
 
+/// What actually gets uploaded per ball. The transform is rebuilt in the vertex shader.
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceRaw {
-    model: [[f32; 4]; 4],
-    color: [f32; 3],
+    position: [f32; 2],
+    /// As half floats, with rotation wrapped to `-PI..PI` where they're most precise.
+    scale_rotation: [u16; 2],
+    /// sRGB with straight alpha, 8 bits each, red in the lowest byte.
+    color: u32,
+    /// Sprite index in the low 16 bits, [`InstanceRaw::NO_SPRITE`] for none, and occlusion
+    /// as 8 bits above it.
+    sprite_occlusion: u32,
 }
 
 impl Instance {
     pub fn to_raw(&self) -> InstanceRaw {
-        let transformation_matrix = 
-            Matrix4::from_translation(self.position.extend(0.0)) 
-            * Matrix4::from(self.rotation)
-            * Matrix4::from_scale(self.scale)
-        ;
-
+        let occlusion = (self.occlusion.clamp(0.0, 1.0) * 255.0).round() as u32;
         InstanceRaw {
-            model: transformation_matrix.into(),
-            color: self.color.into(),
+            position: self.position.into(),
+            scale_rotation: [to_f16_bits(self.scale), to_f16_bits((self.rotation + PI).rem_euclid(TAU) - PI)],
+            color: self.color.to_rgba8(self.alpha),
+            sprite_occlusion: self.sprite.unwrap_or(InstanceRaw::NO_SPRITE) as u32 | (occlusion << 16),
         }
     }
 }
 
 impl InstanceRaw {
+    /// Matches `NO_SPRITE` in the shader.
+    pub const NO_SPRITE: u16 = u16::MAX;
+
+    // Locations 0 and 1 are taken by Vertex
+    const ATTRIBS: [wgpu::VertexAttribute; 4] =
+        wgpu::vertex_attr_array![5 => Float32x2, 6 => Float16x2, 7 => Unorm8x4, 8 => Uint32];
+
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         use std::mem;
         wgpu::VertexBufferLayout {
@@ -40,38 +63,38 @@ impl InstanceRaw {
             // This means that our shaders will only change to use the next
             // instance when the shader starts processing a new instance
             step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    // While our vertex shader only uses locations 0, and 1 now, in later tutorials we'll
-                    // be using 2, 3, and 4, for Vertex. We'll start at slot 5 not conflict with them later
-                    shader_location: 5,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                // A mat4 takes up 4 vertex slots as it is technically 4 vec4s. We need to define a slot
-                // for each vec4. We'll have to reassemble the mat4 in
-                // the shader.
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
-                    shader_location: 9,
-                    format: wgpu::VertexFormat::Float32x3,
-                }
-            ],
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(sprite: Option<u16>, occlusion: f32) -> Instance {
+        Instance {
+            position: Vector2::new(1.0, 2.0),
+            rotation: 0.0,
+            scale: 3.0,
+            color: Color::new(1.0, 0.0, 0.0),
+            alpha: 1.0,
+            layer: 0.0,
+            sprite,
+            occlusion,
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn raw_instance_stays_small() {
+        assert_eq!(std::mem::size_of::<InstanceRaw>(), 20);
+    }
+
+    #[test]
+    fn packs_sprite_and_occlusion() {
+        assert_eq!(instance(None, 0.0).to_raw().sprite_occlusion, 0x0000ffff);
+        assert_eq!(instance(Some(0), 1.0).to_raw().sprite_occlusion, 0x00ff0000);
+        assert_eq!(instance(Some(1234), 0.5).to_raw().sprite_occlusion, 0x0080_0000 | 1234);
+        assert_eq!(instance(Some(7), 3.0).to_raw().sprite_occlusion, 0x00ff_0007);
+    }
+}
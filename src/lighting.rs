@@ -0,0 +1,71 @@
+/// How balls are shaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shading {
+    /// A single color per ball.
+    Flat,
+    /// Lit as if each ball were a sphere.
+    Lit(Lighting),
+}
+
+/// A directional light on balls drawn as spheres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lighting {
+    /// Towards the light, in screen space with y up and z out of the screen.
+    pub(crate) direction: [f32; 3],
+    pub(crate) ambient: f32,
+    pub(crate) diffuse: f32,
+    pub(crate) specular: f32,
+    /// Blinn-Phong exponent, higher makes smaller highlights.
+    pub(crate) shininess: f32,
+    /// Light added around the silhouette.
+    pub(crate) rim: f32,
+    /// Higher keeps the rim light closer to the edge.
+    pub(crate) rim_power: f32,
+    /// How much a ball darkens when surrounded by neighbours, `0.0` for none.
+    pub(crate) occlusion: f32,
+}
+
+impl Lighting {
+    pub const DEFAULT: Self = Self {
+        direction: [-0.4, 0.6, 0.7],
+        ambient: 0.25,
+        diffuse: 0.85,
+        specular: 0.5,
+        shininess: 32.0,
+        rim: 0.2,
+        rim_power: 3.0,
+        occlusion: 0.0,
+    };
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Shading {
+    pub const ALL: [Shading; 3] = [
+        Shading::Flat,
+        Shading::Lit(Lighting::DEFAULT),
+        Shading::Lit(Lighting { occlusion: 0.5, ..Lighting::DEFAULT }),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shading::Flat => "flat",
+            Shading::Lit(lighting) if lighting.occlusion > 0.0 => "lit-ao",
+            Shading::Lit(_) => "lit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|shading| shading.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The next of [`Shading::ALL`] by name, so customized lighting still moves on.
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|shading| shading.name() == self.name()).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
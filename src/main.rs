@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use state::State;
 use winit::{
     event::*,
@@ -12,14 +14,62 @@ pub mod render_state;
 pub mod input_handler;
 pub mod util;
 pub mod uniform;
+pub mod camera;
+pub mod mesh;
+pub mod gpu_buffer;
+pub mod render_target;
+pub mod capture;
 pub mod physics;
 pub mod quadtree;
+pub mod broad_phase;
+pub mod morton;
+pub mod bench;
+pub mod shape;
+pub mod raycast;
+pub mod spawner;
+pub mod headless;
+pub mod recorder;
+pub mod timestep;
+pub mod debug_draw;
+pub mod font;
+pub mod hud;
+pub mod colormap;
+pub mod coloring;
+pub mod trails;
+pub mod post;
+pub mod sprite;
+pub mod image;
+pub mod picture;
+pub mod lighting;
+pub mod pacing;
+pub mod srgb_output;
+pub mod scenery;
+pub mod ghost;
 
 pub fn main() {
-    pollster::block_on(run());
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("bench") {
+        bench::run(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("headless") {
+        headless::run(&args[1..]);
+        return;
+    }
+
+    pollster::block_on(run(&args));
 }
 
-pub async fn run() {
+/// Opens the window. `--record PATH [--every N]` starts recording right away,
+/// `--sprites PATH` draws balls with the PNGs at `PATH`, a file or a directory, and
+/// `--picture PATH [--settle STEPS]` pours in balls that settle into the picture at `PATH`.
+/// `--present fifo|mailbox|immediate` picks how frames are shown, `--fps-cap N` draws at
+/// most `N` frames a second and `--batch N` runs `N` steps every frame as fast as it can.
+/// `--scenery-colors INTERIOR,OUTLINE,OBSTACLE` recolors the boundary and obstacles.
+/// `--lifetime N` fades balls out after `N` steps, `--opacity X` makes them translucent,
+/// `--ghosts N` pours in ghosts that fade over `N` steps and `--layer-order on` draws
+/// ghosts under the balls.
+pub async fn run(args: &[String]) {
     env_logger::init();
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
@@ -27,6 +77,58 @@ pub async fn run() {
 
     let mut app_state = State::new(&window).await;
 
+    if let Some(name) = flag_value(args, "--present") {
+        match pacing::present_mode_from_name(name) {
+            Some(mode) => match app_state.render_state.set_present_mode(mode) {
+                Some(used) if used != mode => eprintln!("Present mode {} isn't supported, using {}", name, pacing::present_mode_name(used)),
+                _ => {}
+            },
+            None => eprintln!("Unknown present mode {}, expected fifo, mailbox or immediate", name),
+        }
+    }
+    let fps_cap = flag_value(args, "--fps-cap").and_then(|n| n.parse().ok());
+    app_state.frame_limiter.set_max_fps(fps_cap);
+    app_state.steps_per_frame = flag_value(args, "--batch").and_then(|n| n.parse().ok()).filter(|&n| n > 0);
+
+    if let Some(colors) = flag_value(args, "--scenery-colors") {
+        match app_state.scenery_style.with_colors(colors) {
+            Some(style) => app_state.scenery_style = style,
+            None => eprintln!("Invalid scenery colors {}, expected INTERIOR,OUTLINE,OBSTACLE like #15171d,#8a93a6,#2e3440", colors),
+        }
+    }
+
+    app_state.ball_lifetime = flag_value(args, "--lifetime").and_then(|n| n.parse().ok()).filter(|&n| n > 0);
+    if let Some(opacity) = flag_value(args, "--opacity").and_then(|x| x.parse::<f32>().ok()) {
+        app_state.ball_opacity = opacity.clamp(0.0, 1.0);
+    }
+    if let Some(lifetime) = flag_value(args, "--ghosts").and_then(|n| n.parse().ok()) {
+        app_state.spawners.push(headless::ghost_spawner(lifetime));
+    }
+    app_state.render_state.set_layer_order(flag_value(args, "--layer-order") == Some("on"));
+
+    if let Some(path) = flag_value(args, "--record") {
+        let every = flag_value(args, "--every").and_then(|n| n.parse().ok()).unwrap_or(1);
+        app_state.start_recording(path, every);
+    }
+
+    if let Some(path) = flag_value(args, "--sprites") {
+        match sprite::SpriteAtlas::load(path) {
+            Ok(atlas) => app_state.set_sprites(&atlas),
+            Err(e) => eprintln!("Couldn't load sprites: {}", e),
+        }
+    }
+
+    if let Some(path) = flag_value(args, "--picture") {
+        match image::Image::load_png(std::path::Path::new(path)) {
+            Ok(image) => {
+                let settle = flag_value(args, "--settle").and_then(|n| n.parse().ok()).unwrap_or(1500);
+                app_state.spawners.push(headless::default_spawner());
+                app_state.paint_picture(&image, settle);
+            }
+            Err(e) => eprintln!("Couldn't load picture: {}", e),
+        }
+    }
+
     event_loop.run(move |event, _, control_flow| match event {
         // Window events
         Event::WindowEvent {
@@ -49,10 +151,14 @@ pub async fn run() {
                 app_state.input_handler.handle_kb_input(input);
             },
 
-            WindowEvent::CursorMoved { device_id: _, position, modifiers: _ } => {
+            WindowEvent::CursorMoved { position, .. } => {
                 app_state.input_handler.handle_cursor_movement(position);
             }
 
+            WindowEvent::MouseWheel { delta, .. } => {
+                app_state.input_handler.handle_mouse_wheel(delta);
+            }
+
             WindowEvent::Resized(physical_size) => {
                 app_state.render_state.resize(*physical_size);
             }
@@ -62,24 +168,31 @@ pub async fn run() {
             _ => {}
         },
 
-        Event::DeviceEvent { 
-            device_id: _, 
-            event
-        } => match event {
-            DeviceEvent::Button { button, state } => {
-                app_state.input_handler.handle_mouse_button(button, state);
-            }
-            _ => {}
+        Event::DeviceEvent {
+            event: DeviceEvent::Button { button, state },
+            ..
+        } => {
+            app_state.input_handler.handle_mouse_button(button, state);
         }
 
         Event::RedrawRequested(window_id) if window_id == window.id() => {
             app_state.update(control_flow);
         },
-        Event::MainEventsCleared => {
-            // RedrawRequested will only trigger once, unless we manually
-            // request it.
-            window.request_redraw();
+        Event::MainEventsCleared => match app_state.frame_limiter.wait_until(Instant::now()) {
+            // Sleep until the frame cap allows the next frame
+            Some(deadline) => *control_flow = ControlFlow::WaitUntil(deadline),
+            None => {
+                *control_flow = ControlFlow::Poll;
+                // RedrawRequested will only trigger once, unless we manually
+                // request it.
+                window.request_redraw();
+            }
         },
         _ => {}
     });
 }
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    args.get(i + 1).map(String::as_str)
+}
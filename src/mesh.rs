@@ -0,0 +1,32 @@
+use wgpu::util::DeviceExt;
+
+use crate::vertex::Vertex;
+
+/// Indexed geometry living on the GPU.
+pub struct Mesh {
+    pub(crate) vertex_buffer: wgpu::Buffer,
+    pub(crate) index_buffer: wgpu::Buffer,
+    pub(crate) num_indices: u32,
+}
+
+impl Mesh {
+    pub fn new(device: &wgpu::Device, label: &str, vertices: &[Vertex], indices: &[u32]) -> Self {
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Vertex Buffer", label)),
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Index Buffer", label)),
+                contents: bytemuck::cast_slice(indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        Self { vertex_buffer, index_buffer, num_indices: indices.len() as u32 }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{physics::Physics, util::Vec2, shape::Aabb};
+
+/// Interleaves the bits of `x` and `y` into a Z-order curve index.
+pub fn morton_code(x: u16, y: u16) -> u32 {
+    spread_bits(x) | (spread_bits(y) << 1)
+}
+
+/// Puts a zero bit between each of the bits of `n`.
+fn spread_bits(n: u16) -> u32 {
+    let mut n = n as u32;
+    n = (n | (n << 8)) & 0x00FF00FF;
+    n = (n | (n << 4)) & 0x0F0F0F0F;
+    n = (n | (n << 2)) & 0x33333333;
+    n = (n | (n << 1)) & 0x55555555;
+    n
+}
+
+/// Morton code of `pos` quantized to 16 bits per axis inside `bounds`.
+pub fn morton_code_in(pos: Vec2, bounds: &Aabb) -> u32 {
+    let quantize = |value: f32, min: f32, size: f32| {
+        if size <= 0.0 { return 0 }
+        (((value - min) / size).clamp(0.0, 1.0) * u16::MAX as f32) as u16
+    };
+
+    morton_code(
+        quantize(pos.x, bounds.pos.x, bounds.size.x),
+        quantize(pos.y, bounds.pos.y, bounds.size.y),
+    )
+}
+
+impl Physics {
+    /// Sorts the ball array along a Z-order curve so balls that are close in space are
+    /// close in memory. Returns the order that was applied, see [`Physics::reorder`].
+    pub fn sort_spatially(&mut self) -> Vec<usize> {
+        let Some(bounds) = self.balls_aabb() else { return Vec::new() };
+
+        let mut keyed = self.balls.iter()
+            .enumerate()
+            .map(|(i, ball)| (morton_code_in(ball.pos, &bounds), i))
+            .collect::<Vec<_>>();
+        keyed.sort_unstable();
+
+        let order = keyed.into_iter().map(|(_, i)| i).collect::<Vec<_>>();
+        self.reorder(&order);
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::{Ball, BallHandle, Boundary};
+
+    #[test]
+    fn interleaves_bits() {
+        assert_eq!(morton_code(0, 0), 0);
+        assert_eq!(morton_code(1, 0), 0b01);
+        assert_eq!(morton_code(0, 1), 0b10);
+        assert_eq!(morton_code(0b11, 0b10), 0b1101);
+        assert_eq!(morton_code(u16::MAX, 0), 0x5555_5555);
+        assert_eq!(morton_code(u16::MAX, u16::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn quantizes_inside_bounds() {
+        let bounds = Aabb::new(Vec2::new(-10.0, -10.0), Vec2::new(20.0, 20.0));
+        assert_eq!(morton_code_in(Vec2::new(-10.0, -10.0), &bounds), 0);
+        assert_eq!(morton_code_in(Vec2::new(10.0, 10.0), &bounds), u32::MAX);
+        // Clamped to the edges
+        assert_eq!(morton_code_in(Vec2::new(-50.0, 50.0), &bounds), morton_code(0, u16::MAX));
+
+        let flat = Aabb::new(Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0));
+        assert_eq!(morton_code_in(Vec2::new(3.0, 3.0), &flat), 0);
+    }
+
+    #[test]
+    fn sorts_along_the_curve() {
+        let mut physics = Physics::default();
+        physics.boundary = Boundary::None;
+        let corners = [(90.0, 90.0), (10.0, 10.0), (90.0, 10.0), (10.0, 90.0)];
+        let handles = corners.map(|(x, y)| physics.add_ball(Ball::new(x, y, 5.0)));
+
+        assert_eq!(physics.sort_spatially(), vec![1, 2, 3, 0]);
+        let positions = physics.balls.iter().map(|ball| (ball.pos.x, ball.pos.y)).collect::<Vec<_>>();
+        assert_eq!(positions, vec![(10.0, 10.0), (90.0, 10.0), (10.0, 90.0), (90.0, 90.0)]);
+
+        for (handle, (x, y)) in handles.into_iter().zip(corners) {
+            assert_eq!(physics.ball(handle).unwrap().pos, Vec2::new(x, y));
+        }
+        assert_eq!(physics.index_of(BallHandle(0)), Some(3));
+
+        // Already sorted
+        assert_eq!(physics.sort_spatially(), vec![0, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+/// Present modes that can be picked by name, in the order `V` cycles through them.
+pub const PRESENT_MODES: [wgpu::PresentMode; 3] = [
+    wgpu::PresentMode::Fifo,
+    wgpu::PresentMode::Mailbox,
+    wgpu::PresentMode::Immediate,
+];
+
+pub fn present_mode_name(mode: wgpu::PresentMode) -> &'static str {
+    match mode {
+        wgpu::PresentMode::Fifo => "fifo",
+        wgpu::PresentMode::Mailbox => "mailbox",
+        wgpu::PresentMode::Immediate => "immediate",
+        _ => "other",
+    }
+}
+
+pub fn present_mode_from_name(name: &str) -> Option<wgpu::PresentMode> {
+    PRESENT_MODES.into_iter().find(|&mode| present_mode_name(mode).eq_ignore_ascii_case(name))
+}
+
+/// Keeps frames from starting more often than a set rate, on top of whatever the present
+/// mode does.
+#[derive(Debug, Clone, Default)]
+pub struct FrameLimiter {
+    /// Shortest time between frames, or no limit if `None`.
+    interval: Option<Duration>,
+    last: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn new(max_fps: Option<u32>) -> Self {
+        let mut limiter = Self::default();
+        limiter.set_max_fps(max_fps);
+        limiter
+    }
+
+    pub fn max_fps(&self) -> Option<u32> {
+        self.interval.map(|interval| (1.0 / interval.as_secs_f64()).round() as u32)
+    }
+
+    /// Limits frames to `max_fps` per second, or lifts the limit if `None` or `0`.
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.interval = max_fps.filter(|&fps| fps > 0).map(|fps| Duration::from_secs(1) / fps);
+    }
+
+    /// When the next frame may start, or `None` if it may start at `now`.
+    pub fn wait_until(&self, now: Instant) -> Option<Instant> {
+        let next = self.last? + self.interval?;
+        (next > now).then_some(next)
+    }
+
+    /// Notes that a frame started at `now`.
+    pub fn frame_started(&mut self, now: Instant) {
+        self.last = match (self.last, self.interval) {
+            // Keep to the schedule unless a frame ran late, so the rate doesn't drift below the cap
+            (Some(last), Some(interval)) if now < last + interval * 2 => Some(last + interval),
+            _ => Some(now),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn no_cap_never_waits() {
+        let start = Instant::now();
+        for fps in [None, Some(0)] {
+            let mut limiter = FrameLimiter::new(fps);
+            assert_eq!(limiter.max_fps(), None);
+            limiter.frame_started(start);
+            assert_eq!(limiter.wait_until(start), None);
+        }
+    }
+
+    #[test]
+    fn first_frame_starts_right_away() {
+        let limiter = FrameLimiter::new(Some(50));
+        assert_eq!(limiter.max_fps(), Some(50));
+        assert_eq!(limiter.wait_until(Instant::now()), None);
+    }
+
+    #[test]
+    fn waits_out_the_interval() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(50));
+        limiter.frame_started(start);
+        assert_eq!(limiter.wait_until(start + 5 * MS), Some(start + 20 * MS));
+        assert_eq!(limiter.wait_until(start + 20 * MS), None);
+        assert_eq!(limiter.wait_until(start + 25 * MS), None);
+    }
+
+    #[test]
+    fn keeps_to_the_schedule_when_woken_late() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(50));
+        limiter.frame_started(start);
+
+        // Every frame starts a little after it could have, which mustn't add up
+        for frame in 1..=100 {
+            let scheduled = start + frame * 20 * MS;
+            assert_eq!(limiter.wait_until(scheduled - MS), Some(scheduled));
+            limiter.frame_started(scheduled + 3 * MS);
+        }
+        assert_eq!(limiter.wait_until(start + 2000 * MS), Some(start + 2020 * MS));
+    }
+
+    #[test]
+    fn starts_over_after_a_long_frame() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(50));
+        limiter.frame_started(start);
+
+        // Rather than rushing frames to catch up with the ones it missed
+        limiter.frame_started(start + 100 * MS);
+        assert_eq!(limiter.wait_until(start + 101 * MS), Some(start + 120 * MS));
+    }
+
+    #[test]
+    fn lifting_the_cap() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(30));
+        limiter.frame_started(start);
+        limiter.set_max_fps(None);
+        assert_eq!(limiter.wait_until(start), None);
+        limiter.set_max_fps(Some(144));
+        assert_eq!(limiter.max_fps(), Some(144));
+    }
+
+    #[test]
+    fn present_mode_names() {
+        for mode in PRESENT_MODES {
+            assert_eq!(present_mode_from_name(present_mode_name(mode)), Some(mode));
+        }
+        assert_eq!(present_mode_from_name("Mailbox"), Some(wgpu::PresentMode::Mailbox));
+        assert_eq!(present_mode_from_name("vsync"), None);
+    }
+}
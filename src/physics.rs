@@ -1,83 +1,338 @@
-use crate::{util::Vec2, quadtree::QuadTree};
+use std::{cell::RefCell, time::Instant};
 
-pub const CENTER_OF_SCREEN: Vec2 = Vec2::new(960.0, 515.0);
-const DAMPING: Vec2 = Vec2::fill(0.9995);
+use crate::{util::Vec2, quadtree::QuadTree, shape::{Aabb, BoundingShape, Circle, closest_point_on_segment}, broad_phase::{BroadPhaseStats, AutoTuner}};
+
+/// Share of velocity and spin kept over a whole step.
+const DAMPING: f32 = 0.9995;
+/// How much of the way to rolling without slipping a ball's spin gets per contact.
+const ROLLING_FRICTION: f32 = 0.1;
+
+/// Stable reference to a ball that survives the ball array being reordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BallHandle(pub(crate) u32);
 
 pub struct Physics {
     pub(crate) balls: Vec<Ball>,
+    /// Handle of the ball at each index of `balls`.
+    pub(crate) handles: Vec<BallHandle>,
+    /// Index into `balls` of every handle ever given out.
+    pub(crate) indices: Vec<Option<usize>>,
+    pub(crate) boundary: Boundary,
+    pub(crate) obstacles: Vec<Obstacle>,
     pub(crate) substeps: u32,
     pub(crate) iterations: u32,
+    pub(crate) quadtree_max_depth: usize,
+    pub(crate) quadtree_max_size: usize,
+    /// Adjusts `quadtree_max_depth`/`quadtree_max_size` every frame when set.
+    pub(crate) auto_tuner: Option<AutoTuner>,
+    pub(crate) broad_phase_stats: BroadPhaseStats,
+    /// Contacts found in the last frame, collected only while this is `Some`.
+    pub(crate) contacts: Option<Vec<Contact>>,
+    /// Tree over the current ball positions, built on demand for queries.
+    ball_tree: RefCell<Option<QuadTree<usize>>>,
 }
 
 impl Physics {
     pub fn new(substeps: u32, iterations: u32) -> Self {
-        Self { balls: Vec::new(), substeps, iterations }
+        Self { substeps, iterations, ..Default::default() }
     }
 
     pub fn update(&mut self) {
+        self.broad_phase_stats = BroadPhaseStats {
+            max_depth: self.quadtree_max_depth,
+            max_size: self.quadtree_max_size,
+            ..Default::default()
+        };
+
+        if let Some(contacts) = self.contacts.as_mut() {
+            contacts.clear();
+        }
+        for ball in self.balls.iter_mut() {
+            ball.prev_pos = ball.pos;
+            ball.prev_angle = ball.angle;
+            ball.age += 1;
+            ball.contacts = 0;
+            ball.pressure = 0.0;
+        }
+
+        let dt = 1.0 / self.substeps as f32;
+        for substep in 0..self.substeps {
+            self.step(dt, substep + 1 == self.substeps);
+        }
+
+        if let Some(tuner) = self.auto_tuner.as_mut() {
+            let params = (self.quadtree_max_depth, self.quadtree_max_size);
+            (self.quadtree_max_depth, self.quadtree_max_size) = tuner.update(&self.broad_phase_stats, self.balls.len(), params);
+        }
+    }
+
+    /// Contacts and per-ball pressure are recorded during the first iteration of the
+    /// `last` substep, before the solver has resolved them.
+    fn step(&mut self, dt: f32, last: bool) {
+        self.ball_tree.take();
+
         for ball in self.balls.iter_mut() {
-            ball.apply();
-            ball.update_pos();
+            ball.apply(dt);
+            ball.update_pos(dt);
+            self.boundary.constrain(ball);
         }
 
-        for _ in 0..self.iterations {
-            for (i, j) in self.broad_phase_collisions().iter() {
-                self.collide(*i, *j);
+        for iteration in 0..self.iterations {
+            let start = Instant::now();
+            let pairs = self.broad_phase_collisions();
+            let record = last && iteration == 0;
+            let mut contacts = 0;
+
+            for (i, j) in pairs.iter() {
+                let Some(contact) = self.collide(*i, *j) else { continue };
+                contacts += 1;
+                if !record { continue }
+
+                for k in [*i, *j] {
+                    self.balls[k].contacts += 1;
+                    self.balls[k].pressure += contact.depth;
+                }
+                if let Some(recorded) = self.contacts.as_mut() {
+                    recorded.push(contact);
+                }
             }
 
+            let stats = &mut self.broad_phase_stats;
+            stats.candidate_pairs += pairs.len();
+            stats.contacts += contacts;
+            stats.time += Instant::now() - start;
+
             for ball in self.balls.iter_mut() {
-                ball.circle_boundary();
+                for obstacle in self.obstacles.iter() {
+                    obstacle.constrain(ball);
+                }
+                self.boundary.constrain(ball);
             }
         }
     }
 
-    fn broad_phase_collisions(&self) -> Vec<(usize, usize)> {
+    fn broad_phase_collisions(&mut self) -> Vec<(usize, usize)> {
+        match self.build_ball_tree() {
+            Some(quad_tree) => {
+                self.broad_phase_stats.tree = quad_tree.stats();
+                quad_tree.get_possible_collisions()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn build_ball_tree(&self) -> Option<QuadTree<usize>> {
+        let bounds = self.boundary.aabb().or_else(|| self.balls_aabb())?;
+
         let mut quad_tree = QuadTree::new(
-            Vec2::new(420.0, 0.0), 
-            Vec2::new(1080.0, 1080.0), 
-            8, 
-            4
+            bounds.pos,
+            bounds.size,
+            self.quadtree_max_depth,
+            self.quadtree_max_size
         );
 
         for (i, ball) in self.balls.iter().enumerate() {
-            quad_tree.insert_ball(&ball, i);
+            quad_tree.insert_ball(ball, i);
         }
 
-        quad_tree.get_possible_collisions()
+        Some(quad_tree)
     }
 
-    pub fn add_ball(&mut self, ball: Ball) {
+    /// Runs `f` with a tree over the current ball positions, rebuilding it if the balls moved.
+    pub(crate) fn with_ball_tree<R>(&self, f: impl FnOnce(Option<&QuadTree<usize>>) -> R) -> R {
+        let mut ball_tree = self.ball_tree.borrow_mut();
+        if ball_tree.is_none() {
+            *ball_tree = self.build_ball_tree();
+        }
+
+        f(ball_tree.as_ref())
+    }
+
+    /// Pairs of balls touching in the last update, each counted once. Unlike
+    /// `broad_phase_stats.contacts` this isn't summed over iterations and substeps.
+    pub fn contact_count(&self) -> usize {
+        self.balls.iter().map(|ball| ball.contacts as usize).sum::<usize>() / 2
+    }
+
+    /// Bounding box of every live ball, or `None` if there are no balls.
+    pub fn balls_aabb(&self) -> Option<Aabb> {
+        self.balls.iter().map(Ball::aabb).reduce(|a, b| a.union(&b))
+    }
+
+    pub fn add_ball(&mut self, ball: Ball) -> BallHandle {
+        self.ball_tree.take();
+
+        let handle = BallHandle(self.indices.len() as u32);
+        self.indices.push(Some(self.balls.len()));
+        self.handles.push(handle);
         self.balls.push(ball);
+
+        handle
+    }
+
+    /// Removes a ball, moving the last ball into its index. Its handle is never given out again.
+    pub fn remove_ball(&mut self, handle: BallHandle) -> Option<Ball> {
+        let index = self.index_of(handle)?;
+        self.ball_tree.take();
+
+        let ball = self.balls.swap_remove(index);
+        self.handles.swap_remove(index);
+        self.indices[handle.0 as usize] = None;
+        if let Some(&moved) = self.handles.get(index) {
+            self.indices[moved.0 as usize] = Some(index);
+        }
+
+        Some(ball)
+    }
+
+    /// Removes every ball, keeping the boundary, obstacles and solver settings. Handles
+    /// start from zero again.
+    pub fn clear(&mut self) {
+        self.ball_tree.take();
+        self.balls.clear();
+        self.handles.clear();
+        self.indices.clear();
+        if let Some(contacts) = self.contacts.as_mut() {
+            contacts.clear();
+        }
+    }
+
+    pub fn index_of(&self, handle: BallHandle) -> Option<usize> {
+        self.indices.get(handle.0 as usize).copied().flatten()
+    }
+
+    pub fn ball(&self, handle: BallHandle) -> Option<&Ball> {
+        self.index_of(handle).map(|i| &self.balls[i])
+    }
+
+    pub fn ball_mut(&mut self, handle: BallHandle) -> Option<&mut Ball> {
+        // The ball may be moved through the reference
+        self.ball_tree.take();
+        self.index_of(handle).map(|i| &mut self.balls[i])
+    }
+
+    /// Moves the ball at `order[i]` to index `i`, keeping handles pointing at the same balls.
+    pub fn reorder(&mut self, order: &[usize]) {
+        debug_assert_eq!(order.len(), self.balls.len());
+        self.ball_tree.take();
+
+        self.balls = order.iter().map(|&i| self.balls[i].clone()).collect();
+        self.handles = order.iter().map(|&i| self.handles[i]).collect();
+
+        for (i, handle) in self.handles.iter().enumerate() {
+            self.indices[handle.0 as usize] = Some(i);
+        }
+    }
+
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        self.obstacles.push(obstacle);
     }
 
     // ewwww
-    fn collide(&mut self, i: usize, j: usize) {
+    /// Pushes two balls apart if they overlap, returning where they touched.
+    fn collide(&mut self, i: usize, j: usize) -> Option<Contact> {
         let ball_1 = self.balls[i].clone();
         let ball_2 = self.balls[j].clone();
 
         let b = &mut self.balls;
 
         let added_radii = ball_1.radius + ball_2.radius;
-        if (ball_1.pos.x - ball_2.pos.x).abs() >= added_radii { return }
-        if (ball_1.pos.y - ball_2.pos.y).abs() >= added_radii { return }
+        if (ball_1.pos.x - ball_2.pos.x).abs() >= added_radii { return None }
+        if (ball_1.pos.y - ball_2.pos.y).abs() >= added_radii { return None }
 
         let distance = ball_1.pos.distance(&ball_2.pos);
-        if distance >= added_radii { return }
+        if distance >= added_radii { return None }
 
         let move_dist = added_radii - distance;
-        let resolution_vec = (ball_1.pos - ball_2.pos).normalize() * Vec2::fill(move_dist * 0.5);
+        let normal = (ball_1.pos - ball_2.pos).normalize();
+        let resolution_vec = normal * Vec2::fill(move_dist * 0.5);
 
         b[i].pos += resolution_vec;
         b[i].vel += resolution_vec;
+        b[i].roll(normal, ball_2.vel);
 
         b[j].pos -= resolution_vec;
         b[j].vel -= resolution_vec;
+        b[j].roll(-normal, ball_1.vel);
+
+        Some(Contact {
+            point: ball_2.pos + normal * (ball_2.radius - move_dist * 0.5),
+            normal,
+            depth: move_dist,
+        })
     }
 }
 
 impl Default for Physics {
     fn default() -> Self {
-        Self { balls: Vec::new(), substeps: 1, iterations: 6 }
+        Self {
+            balls: Vec::new(),
+            handles: Vec::new(),
+            indices: Vec::new(),
+            boundary: Boundary::Circle { center: Vec2::fill(0.0), radius: 500.0 },
+            obstacles: Vec::new(),
+            substeps: 1,
+            iterations: 6,
+            quadtree_max_depth: 8,
+            quadtree_max_size: 4,
+            auto_tuner: None,
+            broad_phase_stats: BroadPhaseStats::default(),
+            contacts: None,
+            ball_tree: RefCell::new(None),
+        }
+    }
+}
+
+/// The container that keeps balls in place.
+#[derive(Debug, Clone, Copy)]
+pub enum Boundary {
+    None,
+    Circle { center: Vec2, radius: f32 },
+}
+
+impl Boundary {
+    pub fn constrain(&self, ball: &mut Ball) {
+        match *self {
+            Boundary::None => {}
+            Boundary::Circle { center, radius } => ball.circle_boundary(center, radius),
+        }
+    }
+
+    /// Bounding box of the area inside the boundary, or `None` if unbounded.
+    pub fn aabb(&self) -> Option<Aabb> {
+        match *self {
+            Boundary::None => None,
+            Boundary::Circle { center, radius } => Some(Circle::new(center, radius).aabb()),
+        }
+    }
+}
+
+/// Two balls found overlapping.
+#[derive(Debug, Clone, Copy)]
+pub struct Contact {
+    /// Middle of the overlap.
+    pub(crate) point: Vec2,
+    /// Points from the second ball towards the first.
+    pub(crate) normal: Vec2,
+    /// How far the balls overlapped.
+    pub(crate) depth: f32,
+}
+
+/// Static geometry that balls bounce off.
+#[derive(Debug, Clone, Copy)]
+pub enum Obstacle {
+    Circle { center: Vec2, radius: f32 },
+    Segment { start: Vec2, end: Vec2 },
+}
+
+impl Obstacle {
+    pub fn constrain(&self, ball: &mut Ball) {
+        match *self {
+            Obstacle::Circle { center, radius } => ball.push_out_of(center, radius),
+            Obstacle::Segment { start, end } => {
+                ball.push_out_of(closest_point_on_segment(ball.pos, start, end), 0.0)
+            }
+        }
     }
 }
 
@@ -86,6 +341,20 @@ pub struct Ball {
     pub(crate) radius: f32,
     pub(crate) pos: Vec2,
     pub(crate) vel: Vec2,
+    /// `pos` before the last update, for drawing between updates.
+    pub(crate) prev_pos: Vec2,
+    /// Frames since the ball was added.
+    pub(crate) age: u32,
+    /// Other balls it touched in the last frame.
+    pub(crate) contacts: u32,
+    /// Total overlap with other balls in the last frame.
+    pub(crate) pressure: f32,
+    /// Orientation in radians, only for drawing.
+    pub(crate) angle: f32,
+    /// Radians turned per step.
+    pub(crate) spin: f32,
+    /// `angle` before the last update.
+    pub(crate) prev_angle: f32,
 }
 
 impl Ball {
@@ -94,29 +363,157 @@ impl Ball {
             radius,
             pos: Vec2::new(x, y),
             vel: Vec2::new(0., 0.),
+            prev_pos: Vec2::new(x, y),
+            age: 0,
+            contacts: 0,
+            pressure: 0.0,
+            angle: 0.0,
+            spin: 0.0,
+            prev_angle: 0.0,
         }
     }
 
-    pub fn apply(&mut self) {
-        self.vel.y += 0.2;
-        self.vel *= DAMPING;
+    pub fn aabb(&self) -> Aabb {
+        Circle::new(self.pos, self.radius).aabb()
     }
 
-    pub fn update_pos(&mut self) {
-        self.pos += self.vel;
-        self.circle_boundary();
+    /// Gravity and damping over `dt` steps. Damping compounds, so it comes to the same
+    /// however many substeps a step is split into.
+    pub fn apply(&mut self, dt: f32) {
+        self.vel.y += 0.2 * dt;
+        let damping = DAMPING.powf(dt);
+        self.vel = self.vel * damping;
+        self.spin *= damping;
+    }
+
+    pub fn update_pos(&mut self, dt: f32) {
+        self.pos += self.vel * dt;
+        self.angle = (self.angle + self.spin * dt) % std::f32::consts::TAU;
+    }
+
+    /// Moves the spin towards rolling along a surface touching the ball. `normal` points from
+    /// the surface towards the ball, which rolls if it slides relative to `surface_vel`.
+    fn roll(&mut self, normal: Vec2, surface_vel: Vec2) {
+        let rolling = normal.perp().dot(&(self.vel - surface_vel)) / self.radius;
+        self.spin += (rolling - self.spin) * ROLLING_FRICTION;
+    }
+
+    /// Moves the ball out of a circle it's overlapping.
+    pub fn push_out_of(&mut self, center: Vec2, radius: f32) {
+        let distance = self.pos.distance(&center);
+        let required_distance = radius + self.radius;
+
+        if distance < required_distance && distance > 0.0 {
+            let move_dist = required_distance - distance;
+            let normal = (self.pos - center).normalize();
+            let resolution_vec = normal * move_dist;
+
+            self.pos += resolution_vec;
+            self.vel += resolution_vec;
+            self.roll(normal, Vec2::fill(0.0));
+        }
     }
 
-    pub fn circle_boundary(&mut self) {
-        let distance = self.pos.distance(&CENTER_OF_SCREEN);
-        let allowed_distance = 500.0 - self.radius;
+    pub fn circle_boundary(&mut self, center: Vec2, radius: f32) {
+        let distance = self.pos.distance(&center);
+        let allowed_distance = radius - self.radius;
 
         if distance > allowed_distance {
             let move_dist = distance - allowed_distance;
-            let resolution_vec = (self.pos - CENTER_OF_SCREEN).normalize() * move_dist;
+            let normal = (self.pos - center).normalize();
+            let resolution_vec = normal * move_dist;
 
             self.pos -= resolution_vec;
             self.vel -= resolution_vec;
+            self.roll(-normal, Vec2::fill(0.0));
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two touching balls around `center`, far from anything else.
+    fn physics_with_pair(boundary: Boundary, center: Vec2) -> Physics {
+        let mut physics = Physics { boundary, ..Default::default() };
+        physics.add_ball(Ball::new(center.x - 4.0, center.y, 5.0));
+        physics.add_ball(Ball::new(center.x + 4.0, center.y, 5.0));
+        physics
+    }
+
+    #[test]
+    fn root_fits_the_boundary() {
+        let center = Vec2::new(-3000.0, 5000.0);
+        let mut physics = physics_with_pair(Boundary::Circle { center, radius: 100.0 }, center);
+        assert_eq!(physics.broad_phase_collisions(), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn root_fits_the_balls_without_a_boundary() {
+        let mut physics = physics_with_pair(Boundary::None, Vec2::new(10000.0, -10000.0));
+        assert_eq!(physics.broad_phase_collisions(), vec![(1, 0)]);
+
+        let mut empty = Physics { boundary: Boundary::None, ..Default::default() };
+        assert!(empty.broad_phase_collisions().is_empty());
+    }
+
+    #[test]
+    fn balls_outside_the_boundary_still_collide() {
+        let mut physics = physics_with_pair(Boundary::Circle { center: Vec2::new(960.0, 515.0), radius: 100.0 }, Vec2::new(0.0, 0.0));
+        assert_eq!(physics.broad_phase_collisions(), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn damping_is_the_same_for_any_substeps() {
+        for substeps in [2, 3, 8] {
+            let mut whole = Ball::new(0.0, 0.0, 1.0);
+            whole.vel = Vec2::new(10.0, 0.0);
+            let mut split = whole.clone();
+
+            whole.apply(1.0);
+            for _ in 0..substeps {
+                split.apply(1.0 / substeps as f32);
+            }
+            assert!((whole.vel.x - split.vel.x).abs() < 1e-4, "{} substeps", substeps);
+        }
+    }
+
+    #[test]
+    fn obstacles_push_balls_out() {
+        let mut ball = Ball::new(12.0, 0.0, 5.0);
+        Obstacle::Circle { center: Vec2::new(0.0, 0.0), radius: 10.0 }.constrain(&mut ball);
+        assert_eq!(ball.pos, Vec2::new(15.0, 0.0));
+
+        let segment = Obstacle::Segment { start: Vec2::new(-10.0, 0.0), end: Vec2::new(10.0, 0.0) };
+        let mut ball = Ball::new(0.0, 3.0, 5.0);
+        segment.constrain(&mut ball);
+        assert_eq!(ball.pos, Vec2::new(0.0, 5.0));
+
+        // Past the end, it's pushed away from the end point
+        let mut ball = Ball::new(13.0, -4.0, 5.0);
+        segment.constrain(&mut ball);
+        assert!((ball.pos.distance(&Vec2::new(10.0, 0.0)) - 5.0).abs() < 1e-5);
+
+        let mut clear = Ball::new(0.0, 6.0, 5.0);
+        segment.constrain(&mut clear);
+        assert_eq!(clear.pos, Vec2::new(0.0, 6.0));
+    }
+
+    #[test]
+    fn handles_follow_balls_through_reorder() {
+        let mut physics = Physics { boundary: Boundary::None, ..Default::default() };
+        let handles = [1.0, 2.0, 3.0].map(|x| physics.add_ball(Ball::new(x, 0.0, 1.0)));
+
+        physics.reorder(&[2, 0, 1]);
+        assert_eq!(physics.balls.iter().map(|ball| ball.pos.x).collect::<Vec<_>>(), vec![3.0, 1.0, 2.0]);
+        assert_eq!(handles.map(|handle| physics.index_of(handle)), [Some(1), Some(2), Some(0)]);
+
+        physics.ball_mut(handles[2]).unwrap().pos.y = 7.0;
+        physics.reorder(&[1, 2, 0]);
+        assert_eq!(physics.ball(handles[2]).unwrap().pos, Vec2::new(3.0, 7.0));
+        assert_eq!(physics.handles, vec![handles[0], handles[1], handles[2]]);
+
+        assert!(physics.ball(BallHandle(3)).is_none());
+    }
+}
@@ -0,0 +1,42 @@
+use crate::{state::State, image::Image, shape::Aabb, util::{Color, Vec2}};
+
+impl State {
+    /// Runs the simulation for `steps` steps without drawing, gives every ball the color of
+    /// `image` where it came to rest, then starts over. Played again, the pile settles into
+    /// the picture.
+    pub fn paint_picture(&mut self, image: &Image, steps: u32) {
+        // The tuner reacts to timings, so the second run would build different trees
+        if self.physics.auto_tuner.take().is_some() {
+            println!("Quadtree auto-tuning turned off for the picture");
+        }
+
+        let spawners = self.spawners.clone();
+        self.replay_colors = None;
+        self.reset();
+        for _ in 0..steps {
+            self.simulate();
+        }
+
+        let mut colors = vec![Color::new(0.0, 0.0, 0.0); self.physics.indices.len()];
+        if let Some(bounds) = self.physics.balls_aabb() {
+            for (ball, handle) in self.physics.balls.iter().zip(self.physics.handles.iter()) {
+                colors[handle.0 as usize] = picture_color(image, &bounds, ball.pos);
+            }
+        }
+
+        self.reset();
+        self.spawners = spawners;
+        self.replay_colors = Some(colors);
+    }
+}
+
+/// The color at `pos` of `image` scaled to cover `bounds` and centered on it, over black.
+fn picture_color(image: &Image, bounds: &Aabb, pos: Vec2) -> Color {
+    let scale = (bounds.size.x / image.width as f32).max(bounds.size.y / image.height as f32);
+    let offset = pos - bounds.center();
+    let u = offset.x / (image.width as f32 * scale) + 0.5;
+    let v = offset.y / (image.height as f32 * scale) + 0.5;
+
+    let [r, g, b, a] = image.sample(u, v).map(|c| c as f32 / 255.0);
+    (Color::new(r, g, b).to_linear() * a).to_srgb()
+}
@@ -0,0 +1,323 @@
+use wgpu::include_wgsl;
+
+use crate::{trails::create_fullscreen_pipeline, gpu_buffer::GrowableBuffer};
+
+/// One step of the post-processing chain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostEffect {
+    /// Blurs what's brighter than `threshold` and adds it back on top.
+    Bloom { threshold: f32, intensity: f32, blur_passes: u32 },
+    /// Maps HDR colors into `0..1` with an ACES curve.
+    Tonemap { exposure: f32 },
+    /// Darkens the corners by up to `strength`, starting `radius` of the way from the center.
+    Vignette { strength: f32, radius: f32 },
+}
+
+impl PostEffect {
+    /// Glowing balls on a dark background.
+    pub fn neon() -> Vec<PostEffect> {
+        vec![
+            PostEffect::Bloom { threshold: 0.3, intensity: 1.5, blur_passes: 3 },
+            PostEffect::Tonemap { exposure: 1.2 },
+            PostEffect::Vignette { strength: 0.6, radius: 0.4 },
+        ]
+    }
+
+    /// Fullscreen passes this effect takes.
+    fn pass_count(&self) -> usize {
+        match *self {
+            PostEffect::Bloom { blur_passes, .. } => 2 + 2 * blur_passes as usize,
+            PostEffect::Tonemap { .. } | PostEffect::Vignette { .. } => 1,
+        }
+    }
+}
+
+/// Renders the scene into an HDR texture and runs a chain of [`PostEffect`]s on the way to
+/// the real target.
+pub struct PostProcessor {
+    effects: Vec<PostEffect>,
+    scene: RenderTexture,
+    /// Effects write into these in turn.
+    ping_pong: [RenderTexture; 2],
+    /// Half resolution, for blurring highlights.
+    bloom: [RenderTexture; 2],
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// A slot of `params_stride` bytes per pass.
+    params_buffer: GrowableBuffer,
+    params_stride: wgpu::BufferAddress,
+    pipelines: PostPipelines,
+}
+
+struct PostPipelines {
+    threshold: wgpu::RenderPipeline,
+    blur: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline,
+    tonemap: wgpu::RenderPipeline,
+    vignette: wgpu::RenderPipeline,
+    /// Into the final target's format and sample count.
+    output: wgpu::RenderPipeline,
+}
+
+/// The view keeps its texture alive.
+struct RenderTexture {
+    view: wgpu::TextureView,
+}
+
+/// One fullscreen pass, waiting for its params to be uploaded.
+struct PostPass<'a> {
+    pipeline: &'a wgpu::RenderPipeline,
+    source: &'a wgpu::TextureView,
+    overlay: &'a wgpu::TextureView,
+    target: &'a wgpu::TextureView,
+    params: [f32; 4],
+}
+
+impl PostProcessor {
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    const PARAMS_SIZE: wgpu::BufferAddress = 16;
+
+    /// `output_samples` is the sample count of the pass [`PostProcessor::draw_output`] draws into.
+    pub fn new(
+        device: &wgpu::Device,
+        (output_format, output_samples): (wgpu::TextureFormat, u32),
+        (width, height): (u32, u32),
+        effects: Vec<PostEffect>,
+    ) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("fullscreen.wgsl"));
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(3),
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |label, fs_entry, format, sample_count| {
+            create_fullscreen_pipeline(device, &layout, &shader, (format, sample_count), label, fs_entry, wgpu::BlendState::REPLACE)
+        };
+        let pipelines = PostPipelines {
+            threshold: pipeline("Bloom Threshold Pipeline", "fs_threshold", Self::HDR_FORMAT, 1),
+            blur: pipeline("Blur Pipeline", "fs_blur", Self::HDR_FORMAT, 1),
+            composite: pipeline("Bloom Composite Pipeline", "fs_composite", Self::HDR_FORMAT, 1),
+            tonemap: pipeline("Tonemap Pipeline", "fs_tonemap", Self::HDR_FORMAT, 1),
+            vignette: pipeline("Vignette Pipeline", "fs_vignette", Self::HDR_FORMAT, 1),
+            output: pipeline("Post Output Pipeline", "fs_copy", output_format, output_samples),
+        };
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let params_stride = (device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress).max(Self::PARAMS_SIZE);
+        let mut params_buffer = GrowableBuffer::new(device, "Post Params Buffer", wgpu::BufferUsages::UNIFORM);
+        params_buffer.reserve(device, params_stride * pass_count(&effects) as wgpu::BufferAddress);
+
+        Self {
+            scene: RenderTexture::new(device, "Scene Texture", width, height),
+            ping_pong: [
+                RenderTexture::new(device, "Post Texture 0", width, height),
+                RenderTexture::new(device, "Post Texture 1", width, height),
+            ],
+            bloom: [
+                RenderTexture::new(device, "Bloom Texture 0", (width / 2).max(1), (height / 2).max(1)),
+                RenderTexture::new(device, "Bloom Texture 1", (width / 2).max(1), (height / 2).max(1)),
+            ],
+            effects,
+            bind_group_layout,
+            sampler,
+            params_buffer,
+            params_stride,
+            pipelines,
+        }
+    }
+
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    pub fn set_effects(&mut self, device: &wgpu::Device, effects: Vec<PostEffect>) {
+        self.params_buffer.reserve(device, self.params_stride * pass_count(&effects) as wgpu::BufferAddress);
+        self.effects = effects;
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.scene = RenderTexture::new(device, "Scene Texture", width, height);
+        for (i, texture) in self.ping_pong.iter_mut().enumerate() {
+            *texture = RenderTexture::new(device, &format!("Post Texture {}", i), width, height);
+        }
+        for (i, texture) in self.bloom.iter_mut().enumerate() {
+            *texture = RenderTexture::new(device, &format!("Bloom Texture {}", i), (width / 2).max(1), (height / 2).max(1));
+        }
+    }
+
+    /// Where the scene should be drawn, in [`PostProcessor::HDR_FORMAT`].
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.scene.view
+    }
+
+    /// Runs the chain on the scene. The result still has to be drawn with
+    /// [`PostProcessor::draw_output`], using the returned bind group.
+    pub fn run(&self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder) -> wgpu::BindGroup {
+        let (passes, result) = self.passes();
+
+        let mut params = vec![0u8; self.params_stride as usize * (passes.len() + 1)];
+        for (i, pass) in passes.iter().enumerate() {
+            let start = i * self.params_stride as usize;
+            params[start..start + Self::PARAMS_SIZE as usize].copy_from_slice(bytemuck::cast_slice(&pass.params));
+        }
+        self.params_buffer.write(queue, 0, &params);
+
+        for (i, pass) in passes.iter().enumerate() {
+            let bind_group = self.bind_group(device, i, pass.source, pass.overlay);
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Post Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: pass.target,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pass.pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        self.bind_group(device, passes.len(), result, result)
+    }
+
+    /// Copies the result of [`PostProcessor::run`] into a pass on the final target, which
+    /// overlays can then be drawn over.
+    pub fn draw_output<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, output: &'a wgpu::BindGroup) {
+        render_pass.set_pipeline(&self.pipelines.output);
+        render_pass.set_bind_group(0, output, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Inputs for the pass using params slot `slot`.
+    fn bind_group(&self, device: &wgpu::Device, slot: usize, source: &wgpu::TextureView, overlay: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(source) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: self.params_buffer.buffer(),
+                        offset: slot as wgpu::BufferAddress * self.params_stride,
+                        size: wgpu::BufferSize::new(Self::PARAMS_SIZE),
+                    }),
+                },
+                wgpu::BindGroupEntry { binding: 3, resource: wgpu::BindingResource::TextureView(overlay) },
+            ],
+        })
+    }
+
+    /// The passes of the whole chain, and the texture the last one writes to.
+    fn passes(&self) -> (Vec<PostPass<'_>>, &wgpu::TextureView) {
+        let pipelines = &self.pipelines;
+        let [bloom_0, bloom_1] = &self.bloom;
+
+        let mut passes = Vec::new();
+        let mut current = &self.scene.view;
+        let mut next = 0;
+
+        for effect in self.effects.iter() {
+            let target = &self.ping_pong[next].view;
+
+            match *effect {
+                PostEffect::Bloom { threshold, intensity, blur_passes } => {
+                    passes.push(PostPass::new(&pipelines.threshold, current, &bloom_0.view, [threshold, 0.0, 0.0, 0.0]));
+                    for _ in 0..blur_passes {
+                        passes.push(PostPass::new(&pipelines.blur, &bloom_0.view, &bloom_1.view, [1.0, 0.0, 0.0, 0.0]));
+                        passes.push(PostPass::new(&pipelines.blur, &bloom_1.view, &bloom_0.view, [0.0, 1.0, 0.0, 0.0]));
+                    }
+
+                    let mut composite = PostPass::new(&pipelines.composite, current, target, [intensity, 0.0, 0.0, 0.0]);
+                    composite.overlay = &bloom_0.view;
+                    passes.push(composite);
+                }
+                PostEffect::Tonemap { exposure } => {
+                    passes.push(PostPass::new(&pipelines.tonemap, current, target, [exposure, 0.0, 0.0, 0.0]));
+                }
+                PostEffect::Vignette { strength, radius } => {
+                    passes.push(PostPass::new(&pipelines.vignette, current, target, [strength, radius, 0.0, 0.0]));
+                }
+            }
+
+            current = target;
+            next = 1 - next;
+        }
+
+        (passes, current)
+    }
+}
+
+impl<'a> PostPass<'a> {
+    fn new(pipeline: &'a wgpu::RenderPipeline, source: &'a wgpu::TextureView, target: &'a wgpu::TextureView, params: [f32; 4]) -> Self {
+        Self { pipeline, source, overlay: source, target, params }
+    }
+}
+
+impl RenderTexture {
+    fn new(device: &wgpu::Device, label: &str, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: PostProcessor::HDR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        Self { view: texture.create_view(&wgpu::TextureViewDescriptor::default()) }
+    }
+}
+
+/// Passes for a whole chain, including the final copy to the target.
+fn pass_count(effects: &[PostEffect]) -> usize {
+    effects.iter().map(PostEffect::pass_count).sum::<usize>() + 1
+}
@@ -1,52 +1,134 @@
-use std::{rc::Rc, collections::HashSet};
+use std::{rc::Rc, collections::HashSet, hash::Hash};
 
-use itertools::Itertools;
-
-use crate::{util::Vec2, physics::Ball};
+use crate::{util::Vec2, physics::Ball, shape::{Aabb, BoundingShape}};
 
+/// A quadtree storing `T`s, each bounded by an `S`.
 #[derive(Debug, Clone)]
-pub struct QuadTree {
-    node: QuadTreeNode,
-    pub(crate) max_depth: usize,
-    pub(crate) max_size: usize,
+pub struct QuadTree<T, S = Aabb> {
+    node: QuadTreeNode<T, S>,
+    /// Entries that aren't fully inside the root node. These get checked against everything.
+    overflow: Vec<QuadTreeEntry<T, S>>,
 }
 
 #[derive(Debug, Clone)]
-pub struct QuadTreeNode {
-    pos: Vec2,
-    size: Vec2,
+pub struct QuadTreeNode<T, S> {
+    bounds: Aabb,
     depth: usize,
-    children: Option<Rc<[QuadTreeNode; 4]>>,
-    contents: Vec<QuadTreeEntry>,
+    children: Option<Rc<[QuadTreeNode<T, S>; 4]>>,
+    contents: Vec<QuadTreeEntry<T, S>>,
     max_size: usize,
     max_depth: usize,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct QuadTreeStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    /// Number of leaves at each depth.
+    pub depth_histogram: Vec<usize>,
+    pub average_leaf_occupancy: f32,
+    pub overflow_count: usize,
+}
+
 #[derive(Debug, Clone)]
-pub struct QuadTreeEntry {
-    pos: Vec2,
-    size: Vec2,
-    ball_index: usize,
+pub struct QuadTreeEntry<T, S> {
+    pub(crate) shape: S,
+    pub(crate) value: T,
 }
 
-impl QuadTree {
+impl<T: Clone, S: BoundingShape> QuadTree<T, S> {
     pub fn new(pos: Vec2, size: Vec2, max_depth: usize, max_size: usize) -> Self {
         Self {
-            node: QuadTreeNode::new(pos, size, 0, max_size, max_depth),
-            max_depth,
-            max_size,
+            node: QuadTreeNode::new(Aabb::new(pos, size), 0, max_size, max_depth),
+            overflow: Vec::new(),
         }
     }
 
-    pub fn insert_ball(&mut self, ball: &Ball, ball_index: usize) {
-        self.insert(QuadTreeEntry::new(ball.pos - Vec2::fill(ball.radius), Vec2::fill(ball.radius * 2.0), ball_index))
+    pub fn insert(&mut self, entry: QuadTreeEntry<T, S>) {
+        if self.node.bounds.contains(&entry.shape.aabb()) {
+            self.node.insert(entry);
+        } else {
+            self.overflow.push(entry);
+        }
     }
 
-    pub fn insert(&mut self, entry: QuadTreeEntry) {
-        self.node.insert(entry);
+    pub fn stats(&self) -> QuadTreeStats {
+        let mut stats = QuadTreeStats {
+            overflow_count: self.overflow.len(),
+            ..Default::default()
+        };
+        let mut leaf_entries = 0;
+        self.node.collect_stats(&mut stats, &mut leaf_entries);
+
+        if stats.leaf_count > 0 {
+            stats.average_leaf_occupancy = leaf_entries as f32 / stats.leaf_count as f32;
+        }
+        stats
     }
 
-    pub fn get_possible_collisions(&self) -> Vec<(usize, usize)> {
+    /// Bounds and depth of every node.
+    pub fn node_bounds(&self) -> Vec<(Aabb, usize)> {
+        let mut bounds = Vec::new();
+        self.node.collect_bounds(&mut bounds);
+        bounds
+    }
+
+    /// Every value whose shape overlaps the given area. May contain duplicates.
+    pub fn query(&self, area: &Aabb) -> Vec<&T> {
+        let mut found = Vec::new();
+        self.node.query_entries(area, &mut found);
+
+        found.into_iter()
+            .chain(self.overflow.iter().filter(|entry| entry.shape.intersects_aabb(area)))
+            .map(|entry| &entry.value)
+            .collect()
+    }
+
+    /// Every value whose shape contains the given point. May contain duplicates.
+    pub fn query_point(&self, point: Vec2) -> Vec<&T> {
+        let mut found = Vec::new();
+        self.node.query_entries(&Aabb::new(point, Vec2::fill(0.0)), &mut found);
+
+        found.into_iter()
+            .chain(self.overflow.iter())
+            .filter(|entry| entry.shape.contains_point(point))
+            .map(|entry| &entry.value)
+            .collect()
+    }
+}
+
+impl<T: Clone, S: BoundingShape> QuadTree<T, S> {
+    /// Walks the nodes a ray (optionally thickened by `radius`) passes through, nearest
+    /// first, and returns the closest value `test` reports a hit distance for.
+    pub fn cast<F>(&self, origin: Vec2, dir: Vec2, max_dist: f32, radius: f32, mut test: F) -> Option<(f32, &T)>
+    where
+        F: FnMut(&T) -> Option<f32>,
+    {
+        let mut best = None;
+
+        for entry in self.overflow.iter() {
+            check_hit(&mut best, entry, max_dist, &mut test);
+        }
+
+        self.node.cast(origin, dir, max_dist, radius, &mut best, &mut test);
+        best
+    }
+}
+
+fn check_hit<'a, T, S, F>(best: &mut Option<(f32, &'a T)>, entry: &'a QuadTreeEntry<T, S>, max_dist: f32, test: &mut F)
+where
+    F: FnMut(&T) -> Option<f32>,
+{
+    let Some(dist) = test(&entry.value) else { return };
+    let closest = best.map_or(max_dist, |(best_dist, _)| best_dist);
+
+    if dist <= closest {
+        *best = Some((dist, &entry.value));
+    }
+}
+
+impl<T: Copy + Ord + Hash, S: BoundingShape> QuadTree<T, S> {
+    pub fn get_possible_collisions(&self) -> Vec<(T, T)> {
         let leaf_contents = self.node.get_leaf_contents();
         let mut collision_set = HashSet::new();
 
@@ -54,27 +136,49 @@ impl QuadTree {
             let len = leaf.len();
             for i in 0..len {
                 for j in (i+1)..len {
-                    let object_i = leaf[i].ball_index;
-                    let object_j = leaf[j].ball_index;
-
-                    if object_i > object_j {
-                        collision_set.insert((object_i, object_j));
-                    } else {
-                        collision_set.insert((object_j, object_i));
+                    if leaf[i].shape.intersects(&leaf[j].shape) {
+                        insert_pair(&mut collision_set, leaf[i].value, leaf[j].value);
                     }
                 }
             }
         }
 
-        collision_set.into_iter().collect()
+        for (i, entry) in self.overflow.iter().enumerate() {
+            let mut found = Vec::new();
+            self.node.query_entries(&entry.shape.aabb(), &mut found);
+
+            for other in found.into_iter().chain(self.overflow[(i+1)..].iter()) {
+                if entry.shape.intersects(&other.shape) {
+                    insert_pair(&mut collision_set, entry.value, other.value);
+                }
+            }
+        }
+
+        // Sorted so the solver sees pairs in the same order every run
+        let mut pairs = collision_set.into_iter().collect::<Vec<_>>();
+        pairs.sort_unstable();
+        pairs
+    }
+}
+
+impl QuadTree<usize, Aabb> {
+    pub fn insert_ball(&mut self, ball: &Ball, ball_index: usize) {
+        self.insert(QuadTreeEntry::new(ball.aabb(), ball_index))
+    }
+}
+
+fn insert_pair<T: Ord + Hash>(collision_set: &mut HashSet<(T, T)>, object_i: T, object_j: T) {
+    if object_i > object_j {
+        collision_set.insert((object_i, object_j));
+    } else {
+        collision_set.insert((object_j, object_i));
     }
 }
 
-impl QuadTreeNode {
-    pub fn new(pos: Vec2, size: Vec2, depth: usize, max_size: usize, max_depth: usize) -> Self {
-        Self { 
-            pos,
-            size,
+impl<T: Clone, S: BoundingShape> QuadTreeNode<T, S> {
+    pub fn new(bounds: Aabb, depth: usize, max_size: usize, max_depth: usize) -> Self {
+        Self {
+            bounds,
             depth,
             children: None,
             contents: Vec::with_capacity(max_size + 1),
@@ -82,19 +186,20 @@ impl QuadTreeNode {
             max_depth,
         }
     }
-    
+
     fn create_child(&self, pos: Vec2, size: Vec2) -> Self {
-        QuadTreeNode::new(pos, size, self.depth + 1, self.max_size, self.max_depth)
+        QuadTreeNode::new(Aabb::new(pos, size), self.depth + 1, self.max_size, self.max_depth)
     }
 
     fn create_children(&self) -> [Self; 4] {
-        let half_size = self.size / 2.0;
-        let half_pos = self.pos + half_size;
-        
+        let pos = self.bounds.pos;
+        let half_size = self.bounds.size / 2.0;
+        let half_pos = pos + half_size;
+
         [
-            self.create_child(self.pos, half_size),
-            self.create_child(Vec2::new(self.pos.x, half_pos.y), half_size),
-            self.create_child(Vec2::new(half_pos.x, self.pos.y), half_size),
+            self.create_child(pos, half_size),
+            self.create_child(Vec2::new(pos.x, half_pos.y), half_size),
+            self.create_child(Vec2::new(half_pos.x, pos.y), half_size),
             self.create_child(half_pos, half_size),
         ]
     }
@@ -103,8 +208,8 @@ impl QuadTreeNode {
         self.children = Some(Rc::new(self.create_children()));
     }
 
-    fn insert(&mut self, entry: QuadTreeEntry) {
-        if !entry.colliding(&self.pos, &self.size) {
+    fn insert(&mut self, entry: QuadTreeEntry<T, S>) {
+        if !entry.shape.intersects_aabb(&self.bounds) {
             return;
         }
 
@@ -114,45 +219,237 @@ impl QuadTreeNode {
             self.contents.push(entry);
             if self.contents.len() > self.max_size && self.depth < self.max_depth {
                 self.split();
-                for object in self.contents.clone().into_iter() {
+                for object in std::mem::take(&mut self.contents).into_iter() {
                     self.push_to_children(object);
                 }
-                self.contents.clear();
             }
         }
     }
 
-    fn push_to_children(&mut self, entry: QuadTreeEntry) {
-        if self.children.is_none() { return }
+    fn push_to_children(&mut self, entry: QuadTreeEntry<T, S>) {
+        let Some(children) = self.children.as_mut() else { return };
 
-        let children = Rc::get_mut(self.children.as_mut().unwrap());
-        for child in children.unwrap().iter_mut() {
+        for child in Rc::get_mut(children).unwrap().iter_mut() {
             child.insert(entry.clone());
         }
     }
 
-    fn get_leaf_contents(&self) -> Vec<Vec<QuadTreeEntry>> {
-        if self.children.is_some() {
-            let mut ret = Vec::new();
-            for child in self.children.as_ref().unwrap().iter() {
-                ret.append(&mut child.get_leaf_contents());
+    fn get_leaf_contents(&self) -> Vec<&[QuadTreeEntry<T, S>]> {
+        match &self.children {
+            Some(children) => {
+                let mut ret = Vec::new();
+                for child in children.iter() {
+                    ret.append(&mut child.get_leaf_contents());
+                }
+                ret
             }
-            return ret
-        } else {
-            return vec![self.contents.clone()]
+            None => vec![self.contents.as_slice()],
         }
     }
+
+    fn collect_stats(&self, stats: &mut QuadTreeStats, leaf_entries: &mut usize) {
+        stats.node_count += 1;
+
+        match &self.children {
+            Some(children) => {
+                for child in children.iter() {
+                    child.collect_stats(stats, leaf_entries);
+                }
+            }
+            None => {
+                if stats.depth_histogram.len() <= self.depth {
+                    stats.depth_histogram.resize(self.depth + 1, 0);
+                }
+                stats.depth_histogram[self.depth] += 1;
+                stats.leaf_count += 1;
+                *leaf_entries += self.contents.len();
+            }
+        }
+    }
+
+    fn collect_bounds(&self, bounds: &mut Vec<(Aabb, usize)>) {
+        bounds.push((self.bounds, self.depth));
+        for child in self.children.iter().flat_map(|children| children.iter()) {
+            child.collect_bounds(bounds);
+        }
+    }
+
+    fn cast<'a, F>(&'a self, origin: Vec2, dir: Vec2, max_dist: f32, radius: f32, best: &mut Option<(f32, &'a T)>, test: &mut F)
+    where
+        F: FnMut(&T) -> Option<f32>,
+    {
+        match &self.children {
+            Some(children) => {
+                let mut hit_children = children.iter()
+                    .filter_map(|child| {
+                        let limit = best.map_or(max_dist, |(best_dist, _)| best_dist);
+                        child.bounds.inflate(radius).ray_entry(origin, dir, limit).map(|t| (t, child))
+                    })
+                    .collect::<Vec<_>>();
+                hit_children.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                for (t, child) in hit_children.into_iter() {
+                    // Everything in this child is further away than what we already hit
+                    if best.is_some_and(|(best_dist, _)| t > best_dist) { break }
+                    child.cast(origin, dir, max_dist, radius, best, test);
+                }
+            }
+            None => {
+                for entry in self.contents.iter() {
+                    check_hit(best, entry, max_dist, test);
+                }
+            }
+        }
+    }
+
+    /// Collects every entry overlapping the given area. May contain duplicates.
+    fn query_entries<'a>(&'a self, area: &Aabb, found: &mut Vec<&'a QuadTreeEntry<T, S>>) {
+        if !area.intersects(&self.bounds) {
+            return;
+        }
+
+        match &self.children {
+            Some(children) => {
+                for child in children.iter() {
+                    child.query_entries(area, found);
+                }
+            }
+            None => {
+                for entry in self.contents.iter() {
+                    if entry.shape.intersects_aabb(area) {
+                        found.push(entry);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T, S> QuadTreeEntry<T, S> {
+    pub fn new(shape: S, value: T) -> Self {
+        Self { shape, value }
+    }
 }
 
-impl QuadTreeEntry {
-    pub fn new(pos: Vec2, size: Vec2, index: usize) -> Self {
-        Self { pos, size, ball_index: index }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::Circle;
+
+    /// A tree over `(0, 0)..(100, 100)` that splits as soon as a node holds two balls.
+    fn tree_with(balls: &[Ball]) -> QuadTree<usize> {
+        let mut tree = QuadTree::new(Vec2::new(0.0, 0.0), Vec2::new(100.0, 100.0), 8, 1);
+        for (i, ball) in balls.iter().enumerate() {
+            tree.insert_ball(ball, i);
+        }
+        tree
+    }
+
+    fn sorted<T: Ord>(mut pairs: Vec<(T, T)>) -> Vec<(T, T)> {
+        pairs.sort();
+        pairs
+    }
+
+    #[test]
+    fn pairs_overlapping_balls_inside_the_root() {
+        let tree = tree_with(&[
+            Ball::new(20.0, 20.0, 5.0),
+            Ball::new(80.0, 80.0, 5.0),
+            Ball::new(27.0, 20.0, 5.0),
+        ]);
+        assert_eq!(sorted(tree.get_possible_collisions()), vec![(2, 0)]);
+    }
+
+    #[test]
+    fn out_of_bounds_ball_pairs_with_balls_inside() {
+        let tree = tree_with(&[
+            Ball::new(10.0, 10.0, 2.0),
+            Ball::new(10.0, 90.0, 2.0),
+            Ball::new(95.0, 50.0, 4.0),
+            // Sticks out past the right edge
+            Ball::new(104.0, 50.0, 6.0),
+            Ball::new(90.0, 10.0, 2.0),
+        ]);
+        assert_eq!(sorted(tree.get_possible_collisions()), vec![(3, 2)]);
+    }
+
+    #[test]
+    fn out_of_bounds_balls_pair_with_each_other() {
+        let tree = tree_with(&[
+            Ball::new(-50.0, -50.0, 5.0),
+            Ball::new(50.0, 50.0, 5.0),
+            Ball::new(-42.0, -50.0, 5.0),
+            Ball::new(500.0, 50.0, 5.0),
+        ]);
+        assert_eq!(sorted(tree.get_possible_collisions()), vec![(2, 0)]);
+    }
+
+    fn sorted_values<T: Ord + Copy>(found: Vec<&T>) -> Vec<T> {
+        let mut values = found.into_iter().copied().collect::<Vec<_>>();
+        values.sort();
+        values.dedup();
+        values
     }
 
-    pub fn colliding(&self, collider_pos: &Vec2, collider_size: &Vec2) -> bool {
-        let translated = *collider_pos - self.pos;
+    /// Circles named by letter, with two close enough to overlap.
+    fn circle_tree() -> QuadTree<char, Circle> {
+        let mut tree = QuadTree::new(Vec2::new(0.0, 0.0), Vec2::new(100.0, 100.0), 8, 1);
+        for (name, x, y, radius) in [('a', 20.0, 20.0, 5.0), ('b', 80.0, 80.0, 10.0), ('c', 28.0, 20.0, 5.0), ('d', 150.0, 20.0, 5.0)] {
+            tree.insert(QuadTreeEntry::new(Circle::new(Vec2::new(x, y), radius), name));
+        }
+        tree
+    }
 
-        // don't ask
-        translated.both_less_eq(&self.size) && (-translated).both_less_eq(collider_size)
+    #[test]
+    fn query_any_payload() {
+        let tree = circle_tree();
+        assert_eq!(sorted_values(tree.query(&Aabb::new(Vec2::new(0.0, 0.0), Vec2::new(30.0, 30.0)))), vec!['a', 'c']);
+        assert_eq!(sorted_values(tree.query(&Aabb::new(Vec2::new(60.0, 60.0), Vec2::new(100.0, 100.0)))), vec!['b']);
+        // Outside the root, so only in the overflow bucket
+        assert_eq!(sorted_values(tree.query(&Aabb::new(Vec2::new(140.0, 0.0), Vec2::new(20.0, 40.0)))), vec!['d']);
+        assert!(tree.query(&Aabb::new(Vec2::new(40.0, 40.0), Vec2::new(10.0, 10.0))).is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn query_uses_the_exact_shape() {
+        let tree = circle_tree();
+        // Inside the bounding box of `b`, but outside the circle
+        assert!(tree.query(&Aabb::new(Vec2::new(71.0, 71.0), Vec2::new(1.0, 1.0))).is_empty());
+        assert!(tree.query_point(Vec2::new(71.0, 71.0)).is_empty());
+
+        assert_eq!(sorted_values(tree.query_point(Vec2::new(24.0, 20.0))), vec!['a', 'c']);
+        assert_eq!(sorted_values(tree.query_point(Vec2::new(152.0, 22.0))), vec!['d']);
+    }
+
+    #[test]
+    fn collisions_use_the_exact_shape() {
+        let mut tree = circle_tree();
+        // Bounding boxes overlap `a` at the corner, circles don't
+        tree.insert(QuadTreeEntry::new(Circle::new(Vec2::new(12.0, 12.0), 5.0), 'e'));
+        assert_eq!(sorted(tree.get_possible_collisions()), vec![('c', 'a')]);
+    }
+
+    #[test]
+    fn cast_returns_the_nearest_hit() {
+        let tree = circle_tree();
+        // Distances along a ray from (0, 20) to the right, which passes `a`, `c` and `d`
+        let distance = |name: &char| match name {
+            'a' => Some(15.0),
+            'c' => Some(23.0),
+            'd' => Some(145.0),
+            _ => None,
+        };
+
+        let mut tested = Vec::new();
+        let hit = tree.cast(Vec2::new(0.0, 20.0), Vec2::new(1.0, 0.0), 1000.0, 0.0, |name| {
+            tested.push(*name);
+            distance(name)
+        });
+        assert_eq!(hit, Some((15.0, &'a')));
+        // Never reaches the node holding `b`
+        assert!(!tested.contains(&'b'));
+
+        assert_eq!(tree.cast(Vec2::new(0.0, 20.0), Vec2::new(1.0, 0.0), 10.0, 0.0, distance), None);
+        assert_eq!(tree.cast(Vec2::new(0.0, 20.0), Vec2::new(-1.0, 0.0), 1000.0, 0.0, |_| None), None);
+    }
+}
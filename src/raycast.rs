@@ -0,0 +1,295 @@
+use crate::{physics::{Physics, Obstacle, Boundary, BallHandle}, shape::closest_point_on_segment, util::Vec2};
+
+/// What a cast ran into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HitTarget {
+    Ball(BallHandle),
+    Obstacle(usize),
+    Boundary,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub target: HitTarget,
+    /// Where the cast touches the target.
+    pub point: Vec2,
+    /// Surface normal of the target at `point`, facing the cast.
+    pub normal: Vec2,
+    /// How far the cast travelled before hitting.
+    pub distance: f32,
+}
+
+impl Physics {
+    /// First thing a ray from `origin` towards `dir` hits within `max_dist`.
+    pub fn raycast(&self, origin: Vec2, dir: Vec2, max_dist: f32) -> Option<RayHit> {
+        self.sweep_circle(origin, dir, 0.0, max_dist)
+    }
+
+    /// First thing a circle of `radius` moving from `origin` towards `dir` touches within `max_dist`.
+    pub fn sweep_circle(&self, origin: Vec2, dir: Vec2, radius: f32, max_dist: f32) -> Option<RayHit> {
+        if dir.length() == 0.0 { return None }
+        let dir = dir.normalize();
+
+        let mut best: Option<(f32, HitTarget)> = None;
+        let mut consider = |t: f32, target: HitTarget| {
+            if t <= best.map_or(max_dist, |(best_t, _)| best_t) {
+                best = Some((t, target));
+            }
+        };
+
+        let ball_hit = self.with_ball_tree(|tree| {
+            let tree = tree?;
+            tree.cast(origin, dir, max_dist, radius, |&i| {
+                let ball = &self.balls[i];
+                ray_circle(origin, dir, ball.pos, ball.radius + radius)
+            }).map(|(t, &i)| (t, i))
+        });
+        if let Some((t, i)) = ball_hit {
+            consider(t, HitTarget::Ball(self.handles[i]));
+        }
+
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            let t = match *obstacle {
+                Obstacle::Circle { center, radius: obstacle_radius } => {
+                    ray_circle(origin, dir, center, obstacle_radius + radius)
+                }
+                Obstacle::Segment { start, end } => ray_capsule(origin, dir, start, end, radius),
+            };
+
+            if let Some(t) = t {
+                consider(t, HitTarget::Obstacle(i));
+            }
+        }
+
+        if let Boundary::Circle { center, radius: boundary_radius } = self.boundary {
+            if let Some(t) = ray_circle_inside(origin, dir, center, boundary_radius - radius) {
+                consider(t, HitTarget::Boundary);
+            }
+        }
+
+        let (distance, target) = best?;
+        let center = origin + dir * distance;
+        let normal = self.surface_normal(target, center, dir);
+
+        Some(RayHit {
+            target,
+            point: center - normal * radius,
+            normal,
+            distance,
+        })
+    }
+
+    /// Normal of `target` facing a cast whose circle is centered at `center` when it touches.
+    fn surface_normal(&self, target: HitTarget, center: Vec2, dir: Vec2) -> Vec2 {
+        let normal = match target {
+            HitTarget::Ball(handle) => self.ball(handle).map_or(-dir, |ball| center - ball.pos),
+            HitTarget::Obstacle(i) => match self.obstacles[i] {
+                Obstacle::Circle { center: obstacle_center, .. } => center - obstacle_center,
+                Obstacle::Segment { start, end } => {
+                    center - closest_point_on_segment(center, start, end)
+                }
+            },
+            HitTarget::Boundary => match self.boundary {
+                Boundary::Circle { center: boundary_center, .. } => boundary_center - center,
+                Boundary::None => -dir,
+            },
+        };
+
+        // A cast starting inside something has no meaningful surface to report
+        if normal.length() == 0.0 { -dir } else { normal.normalize() }
+    }
+}
+
+/// Distance along a normalized ray to where it enters a circle. A ray starting inside hits at `0.0`.
+fn ray_circle(origin: Vec2, dir: Vec2, center: Vec2, radius: f32) -> Option<f32> {
+    if origin.distance(&center) <= radius { return Some(0.0) }
+
+    let m = origin - center;
+    let b = m.dot(&dir);
+    let c = m.dot(&m) - radius * radius;
+
+    if b > 0.0 { return None }
+
+    let discriminant = b * b - c;
+    if discriminant < 0.0 { return None }
+
+    Some(-b - discriminant.sqrt())
+}
+
+/// Distance along a normalized ray to where it leaves a circle. A ray starting outside is
+/// already past the edge, so it hits at `0.0`.
+fn ray_circle_inside(origin: Vec2, dir: Vec2, center: Vec2, radius: f32) -> Option<f32> {
+    if origin.distance(&center) >= radius { return Some(0.0) }
+
+    let m = origin - center;
+    let b = m.dot(&dir);
+    let c = m.dot(&m) - radius * radius;
+
+    Some(-b + (b * b - c).sqrt())
+}
+
+/// Distance along a normalized ray to where it enters the capsule around a segment. A ray
+/// starting inside hits at `0.0`.
+fn ray_capsule(origin: Vec2, dir: Vec2, start: Vec2, end: Vec2, radius: f32) -> Option<f32> {
+    // Otherwise the sides would only give the far side, where it leaves
+    if origin.distance(&closest_point_on_segment(origin, start, end)) <= radius { return Some(0.0) }
+
+    let mut best = [
+        ray_circle(origin, dir, start, radius),
+        ray_circle(origin, dir, end, radius),
+    ].into_iter().flatten().reduce(f32::min);
+
+    let segment = end - start;
+    let length = segment.length();
+    if length == 0.0 { return best }
+
+    let along = segment / length;
+    let normal = along.perp();
+
+    // The two long sides of the capsule
+    for side in [radius, -radius] {
+        let offset = start + normal * side;
+        let denom = dir.dot(&normal);
+        if denom == 0.0 { continue }
+
+        let t = (offset - origin).dot(&normal) / denom;
+        if t < 0.0 { continue }
+
+        let projected = (origin + dir * t - start).dot(&along);
+        if (0.0..=length).contains(&projected) {
+            best = Some(best.map_or(t, |b| b.min(t)));
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::Ball;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    /// Unbounded, with balls of radius 10 along the x axis.
+    fn physics_with_balls(xs: &[f32]) -> Physics {
+        let mut physics = Physics::default();
+        physics.boundary = Boundary::None;
+        for &x in xs {
+            physics.add_ball(Ball::new(x, 0.0, 10.0));
+        }
+        physics
+    }
+
+    #[test]
+    fn ray_hits_the_nearest_ball_first() {
+        let physics = physics_with_balls(&[100.0, 50.0, 200.0, -80.0]);
+
+        let hit = physics.raycast(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), 1000.0).unwrap();
+        assert_eq!(hit.target, HitTarget::Ball(BallHandle(1)));
+        assert!(close(hit.distance, 40.0));
+        assert_eq!(hit.point, Vec2::new(40.0, 0.0));
+        assert_eq!(hit.normal, Vec2::new(-1.0, 0.0));
+
+        let hit = physics.raycast(Vec2::new(300.0, 0.0), Vec2::new(-2.0, 0.0), 1000.0).unwrap();
+        assert_eq!(hit.target, HitTarget::Ball(BallHandle(2)));
+        assert!(close(hit.distance, 90.0));
+
+        let hit = physics.raycast(Vec2::new(0.0, 0.0), Vec2::new(-1.0, 0.0), 1000.0).unwrap();
+        assert_eq!(hit.target, HitTarget::Ball(BallHandle(3)));
+    }
+
+    #[test]
+    fn ray_misses() {
+        let physics = physics_with_balls(&[100.0, 50.0]);
+        assert!(physics.raycast(Vec2::new(0.0, 0.0), Vec2::new(0.0, 1.0), 1000.0).is_none());
+        assert!(physics.raycast(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), 30.0).is_none());
+        assert!(physics.raycast(Vec2::new(0.0, 0.0), Vec2::new(0.0, 0.0), 1000.0).is_none());
+        // Passes just above the balls
+        assert!(physics.raycast(Vec2::new(0.0, -10.5), Vec2::new(1.0, 0.0), 1000.0).is_none());
+    }
+
+    #[test]
+    fn ray_sees_balls_moved_by_handle() {
+        let mut physics = physics_with_balls(&[50.0]);
+        assert!(physics.raycast(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), 1000.0).is_some());
+
+        physics.ball_mut(BallHandle(0)).unwrap().pos = Vec2::new(0.0, 50.0);
+        assert!(physics.raycast(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), 1000.0).is_none());
+        let hit = physics.raycast(Vec2::new(0.0, 0.0), Vec2::new(0.0, 1.0), 1000.0).unwrap();
+        assert!(close(hit.distance, 40.0));
+    }
+
+    #[test]
+    fn sweep_touches_earlier_than_a_ray() {
+        let physics = physics_with_balls(&[50.0]);
+        let hit = physics.sweep_circle(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), 5.0, 1000.0).unwrap();
+        assert!(close(hit.distance, 35.0));
+        assert_eq!(hit.point, Vec2::new(40.0, 0.0));
+
+        // Grazes the ball the ray would pass
+        let hit = physics.sweep_circle(Vec2::new(0.0, -14.0), Vec2::new(1.0, 0.0), 5.0, 1000.0);
+        assert!(hit.is_some());
+    }
+
+    #[test]
+    fn starting_inside_hits_right_away() {
+        let mut physics = physics_with_balls(&[0.0]);
+        let hit = physics.raycast(Vec2::new(3.0, 0.0), Vec2::new(1.0, 0.0), 1000.0).unwrap();
+        assert_eq!((hit.target, hit.distance), (HitTarget::Ball(BallHandle(0)), 0.0));
+
+        physics.balls.clear();
+        physics.add_ball(Ball::new(500.0, 500.0, 1.0));
+        physics.add_obstacle(Obstacle::Circle { center: Vec2::new(0.0, 0.0), radius: 20.0 });
+        physics.add_obstacle(Obstacle::Segment { start: Vec2::new(100.0, 0.0), end: Vec2::new(200.0, 0.0) });
+
+        let hit = physics.raycast(Vec2::new(5.0, 5.0), Vec2::new(0.0, 1.0), 1000.0).unwrap();
+        assert_eq!((hit.target, hit.distance), (HitTarget::Obstacle(0), 0.0));
+
+        // Inside the side of the capsule, facing away from the segment
+        let hit = physics.sweep_circle(Vec2::new(150.0, 5.0), Vec2::new(0.0, 1.0), 10.0, 1000.0).unwrap();
+        assert_eq!((hit.target, hit.distance), (HitTarget::Obstacle(1), 0.0));
+        let hit = physics.sweep_circle(Vec2::new(150.0, -5.0), Vec2::new(1.0, 0.0), 10.0, 1000.0).unwrap();
+        assert_eq!((hit.target, hit.distance), (HitTarget::Obstacle(1), 0.0));
+    }
+
+    #[test]
+    fn ray_parallel_to_a_segment() {
+        let mut physics = physics_with_balls(&[]);
+        physics.add_obstacle(Obstacle::Segment { start: Vec2::new(0.0, -10.0), end: Vec2::new(100.0, -10.0) });
+
+        assert!(physics.raycast(Vec2::new(-50.0, 0.0), Vec2::new(1.0, 0.0), 1000.0).is_none());
+
+        // Wide enough to catch the rounded end, which it reaches before the flat side
+        let hit = physics.sweep_circle(Vec2::new(-50.0, 0.0), Vec2::new(1.0, 0.0), 15.0, 1000.0).unwrap();
+        assert_eq!(hit.target, HitTarget::Obstacle(0));
+        assert!(close(hit.distance, 50.0 - 125f32.sqrt()));
+
+        // Along the segment itself
+        let hit = physics.raycast(Vec2::new(-50.0, -10.0), Vec2::new(1.0, 0.0), 1000.0).unwrap();
+        assert!(close(hit.distance, 50.0));
+    }
+
+    #[test]
+    fn boundary_seen_from_inside() {
+        let physics = Physics::default();
+        let Boundary::Circle { center, radius } = physics.boundary else { panic!("expected a circle") };
+
+        let hit = physics.raycast(center, Vec2::new(1.0, 0.0), 1000.0).unwrap();
+        assert_eq!(hit.target, HitTarget::Boundary);
+        assert!(close(hit.distance, radius));
+        assert_eq!(hit.normal, Vec2::new(-1.0, 0.0));
+
+        let hit = physics.sweep_circle(center, Vec2::new(0.0, 1.0), 10.0, 1000.0).unwrap();
+        assert!(close(hit.distance, radius - 10.0));
+        assert!(close(hit.point.y, center.y + radius));
+
+        assert!(physics.raycast(center, Vec2::new(1.0, 0.0), radius - 1.0).is_none());
+
+        let outside = center + Vec2::new(radius + 10.0, 0.0);
+        let hit = physics.raycast(outside, Vec2::new(1.0, 0.0), 1000.0).unwrap();
+        assert_eq!((hit.target, hit.distance), (HitTarget::Boundary, 0.0));
+    }
+}
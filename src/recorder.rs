@@ -0,0 +1,130 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::{Path, PathBuf}};
+
+use crate::capture::FrameImage;
+
+/// Where recorded frames go.
+pub enum RecordFormat {
+    /// Numbered PNGs in a directory.
+    Png { dir: PathBuf },
+    /// One uncompressed YUV 4:4:4 stream, playable with ffplay or mpv.
+    Y4m { writer: BufWriter<File>, header_written: bool },
+}
+
+/// Saves every `every`th frame passed to it.
+pub struct Recorder {
+    format: RecordFormat,
+    pub(crate) every: u32,
+    /// Simulation steps per second.
+    pub(crate) fps: u32,
+    /// Simulation steps between two frames passed to it. Together with `fps` and `every`
+    /// this gives the Y4M frame rate, so it plays back in real time.
+    pub(crate) steps_per_frame: u32,
+    frame: u64,
+    saved: u64,
+    /// Size of the first frame, the rest must match for Y4M.
+    size: Option<(u32, u32)>,
+}
+
+impl Recorder {
+    /// Writes a Y4M stream if `path` ends in `.y4m`, otherwise PNGs into the directory `path`.
+    pub fn new(path: impl AsRef<Path>, every: u32, fps: u32, steps_per_frame: u32) -> io::Result<Self> {
+        let path = path.as_ref();
+        let format = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("y4m")) {
+            RecordFormat::Y4m { writer: BufWriter::new(File::create(path)?), header_written: false }
+        } else {
+            std::fs::create_dir_all(path)?;
+            RecordFormat::Png { dir: path.to_path_buf() }
+        };
+
+        Ok(Self { format, every: every.max(1), fps, steps_per_frame: steps_per_frame.max(1), frame: 0, saved: 0, size: None })
+    }
+
+    /// Counts a frame, calling `capture` and saving the image only if it's one of the frames to keep.
+    pub fn record(&mut self, capture: impl FnOnce() -> Option<FrameImage>) -> io::Result<()> {
+        let wanted = self.frame.is_multiple_of(self.every as u64);
+        self.frame += 1;
+        if !wanted { return Ok(()) }
+
+        let Some(image) = capture() else { return Ok(()) };
+
+        let size = *self.size.get_or_insert((image.width, image.height));
+        match &mut self.format {
+            RecordFormat::Png { dir } => {
+                let path = dir.join(format!("frame_{:05}.png", self.saved));
+                image.save_png(path).map_err(io::Error::other)?;
+            }
+            RecordFormat::Y4m { writer, header_written } => {
+                if size != (image.width, image.height) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Frame size changed during Y4M recording"));
+                }
+
+                if !*header_written {
+                    // Frame rate as a fraction, since each kept frame is `every` frames of
+                    // `steps_per_frame` steps apart
+                    let step_count = self.every * self.steps_per_frame;
+                    writeln!(writer, "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444", image.width, image.height, self.fps, step_count)?;
+                    *header_written = true;
+                }
+
+                writer.write_all(b"FRAME\n")?;
+                writer.write_all(&rgba_to_yuv444(&image.rgba))?;
+            }
+        }
+
+        self.saved += 1;
+        Ok(())
+    }
+
+    pub fn saved(&self) -> u64 {
+        self.saved
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self.format {
+            RecordFormat::Png { .. } => Ok(()),
+            RecordFormat::Y4m { mut writer, .. } => writer.flush(),
+        }
+    }
+}
+
+/// Converts to planar BT.601 limited range YUV.
+fn rgba_to_yuv444(rgba: &[u8]) -> Vec<u8> {
+    let pixels = rgba.len() / 4;
+    let mut yuv = vec![0; pixels * 3];
+    let (y_plane, uv) = yuv.split_at_mut(pixels);
+    let (u_plane, v_plane) = uv.split_at_mut(pixels);
+
+    for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+        let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+        y_plane[i] = (16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8;
+        u_plane[i] = (128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8;
+        v_plane[i] = (128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8;
+    }
+
+    yuv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn y4m_header(every: u32, steps_per_frame: u32) -> String {
+        let path = std::env::temp_dir().join(format!("wgpu_balls_header_{}_{}.y4m", every, steps_per_frame));
+        let mut recorder = Recorder::new(&path, every, 60, steps_per_frame).unwrap();
+        recorder.record(|| Some(FrameImage { width: 2, height: 1, rgba: vec![255; 8] })).unwrap();
+        recorder.finish().unwrap();
+
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let header = contents.split(|&byte| byte == b'\n').next().unwrap();
+        String::from_utf8(header.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn y4m_plays_back_in_simulated_time() {
+        assert_eq!(y4m_header(1, 1), "YUV4MPEG2 W2 H1 F60:1 Ip A1:1 C444");
+        assert_eq!(y4m_header(2, 1), "YUV4MPEG2 W2 H1 F60:2 Ip A1:1 C444");
+        // Batched frames cover more time each
+        assert_eq!(y4m_header(2, 5), "YUV4MPEG2 W2 H1 F60:10 Ip A1:1 C444");
+    }
+}
@@ -1,26 +1,86 @@
+use std::ops::Range;
+
 use wgpu::{include_wgsl, util::DeviceExt};
 use winit::{
     event::*,
     window::Window,
 };
 
-use crate::{vertex::Vertex, instance::{Instance, InstanceRaw}, util::RenderCircle, uniform::VpSizeUniform};
+use crate::{vertex::Vertex, instance::{Instance, InstanceRaw}, util::{RenderCircle, RenderQuad, Vec2}, uniform::{CameraUniform, CircleStyleUniform, LightingUniform}, lighting::Shading, camera::Camera, mesh::Mesh, gpu_buffer::GrowableBuffer, render_target::{RenderTarget, color_attachment, multisampled_view}, capture::{self, FrameImage}, trails::Trails, post::{PostProcessor, PostEffect}, sprite::{SpriteAtlas, SpriteBindings}, pacing::PRESENT_MODES, srgb_output::SrgbOutput};
+
+/// How balls get turned into pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircleMode {
+    /// Triangle fans, aliased edges.
+    Mesh,
+    /// Quads shaded with a signed distance function, anti-aliased edges.
+    Sdf,
+}
 
 pub struct RenderState {
-    surface: wgpu::Surface,
+    target: RenderTarget,
+    /// Encodes frames into the target when its format doesn't do it itself.
+    srgb_output: Option<SrgbOutput>,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
     render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer, 
+    sdf_pipeline: wgpu::RenderPipeline,
+    /// Kept to rebuild the ball pipelines when the scene format changes.
+    shader: wgpu::ShaderModule,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    /// Adds the sprite atlas to `render_pipeline_layout`.
+    ball_pipeline_layout: wgpu::PipelineLayout,
+    sprites: SpriteBindings,
+    line_pipeline: wgpu::RenderPipeline,
+    scenery_pipeline: wgpu::RenderPipeline,
+    hud_pipeline: wgpu::RenderPipeline,
+    circle_mesh: Mesh,
+    quad_mesh: Mesh,
+    pub(crate) circle_mode: CircleMode,
     pub(crate) instances: Vec<Instance>,
-    instance_buffer: wgpu::Buffer,
-    viewport_size_uniform: VpSizeUniform,
+    /// Drawn after `instances` and uploaded every frame, see [`Ghost`](crate::ghost::Ghost).
+    ghost_instances: Vec<Instance>,
+    /// Draws instances from the lowest [`Instance::layer`] up instead of in the order they're
+    /// stored, so translucent ones composite over the right things.
+    layer_order: bool,
+    instance_buffer: GrowableBuffer,
+    /// Instances that changed since they were last uploaded.
+    dirty_instances: Option<Range<usize>>,
+    pub(crate) camera: Camera,
+    /// What's currently in `uniform_buffer`, so the camera is only uploaded when it changes.
+    camera_uniform: CameraUniform,
     uniform_buffer: wgpu::Buffer,
+    circle_style: CircleStyleUniform,
+    circle_style_buffer: wgpu::Buffer,
+    shading: Shading,
+    lighting_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
+    /// Debug overlay lines, see [`DebugDraw`](crate::debug_draw::DebugDraw).
+    line_buffer: GrowableBuffer,
+    line_vertex_count: u32,
+    /// Boundary and obstacle triangles, see [`Scenery`](crate::scenery::Scenery).
+    scenery_buffer: GrowableBuffer,
+    scenery_vertex_count: u32,
+    /// Screen space HUD triangles, see [`Hud`](crate::hud::Hud).
+    hud_buffer: GrowableBuffer,
+    hud_vertex_count: u32,
+    /// Accumulates balls over several frames when set.
+    trails: Option<Trails>,
+    /// Post-processing, which moves the balls and trails into an HDR texture when set.
+    post: Option<PostProcessor>,
+    /// Extra offscreen copy of the window for capturing, created on first use.
+    capture_target: Option<RenderTarget>,
+    /// Samples per pixel for everything drawn with a mesh, lines and the HUD included.
+    sample_count: u32,
+    /// Every count in [`SAMPLE_COUNTS`] the adapter can draw and resolve in the target's format.
+    supported_sample_counts: Vec<u32>,
+    /// The same for [`PostProcessor::HDR_FORMAT`], which some adapters can't multisample.
+    hdr_sample_counts: Vec<u32>,
+    /// Multisampled stand-ins for the scene texture and the target, when `sample_count` is above one.
+    /// The scene one is only needed with post-processing, otherwise both are the target.
+    msaa_scene: Option<wgpu::TextureView>,
+    msaa_target: Option<wgpu::TextureView>,
     pub(crate) size: winit::dpi::PhysicalSize<u32>,
-    pub(crate) num_indices: u32,
 }
 
 impl RenderState {
@@ -30,33 +90,41 @@ impl RenderState {
         let instance = wgpu::Instance::new(wgpu::Backends::all());
         let surface = unsafe { instance.create_surface(window) };
 
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            },
-        ).await.unwrap();
+        let adapter = request_adapter(&instance, Some(&surface)).await
+            .expect("No graphics adapter can draw to this window");
+        let (device, queue) = request_device(&adapter).await;
 
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-                label: None,
-            },
-            None, // Trace path
-        ).await.unwrap();
+        // Prefer a format that encodes sRGB itself, see `SrgbOutput` for the others
+        let formats = surface.get_supported_formats(&adapter);
+        let format = formats.iter().copied().find(|format| format.describe().srgb).unwrap_or(formats[0]);
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_supported_formats(&adapter)[0],
+            format,
             width: size.width,
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
         };
         surface.configure(&device, &config);
 
-        let uniform = VpSizeUniform::new(size);
+        let present_modes = supported_present_modes(&surface, &adapter);
+        Self::with_target(&adapter, device, queue, RenderTarget::Surface { surface, config, present_modes }, size)
+    }
+
+    /// Draws into an offscreen texture instead of a window. Returns `None` if there's no
+    /// usable adapter, software ones included.
+    pub async fn new_headless(width: u32, height: u32) -> Option<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = request_adapter(&instance, None).await?;
+        let (device, queue) = request_device(&adapter).await;
+
+        let target = RenderTarget::offscreen(&device, RenderTarget::OFFSCREEN_FORMAT, width, height);
+        Some(Self::with_target(&adapter, device, queue, target, winit::dpi::PhysicalSize::new(width, height)))
+    }
+
+    fn with_target(adapter: &wgpu::Adapter, device: wgpu::Device, queue: wgpu::Queue, target: RenderTarget, size: winit::dpi::PhysicalSize<u32>) -> Self {
+        let camera = Camera::new(Vec2::new(size.width as f32, size.height as f32));
+        let uniform = camera.to_uniform();
 
         let uniform_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
@@ -66,6 +134,25 @@ impl RenderState {
             }
         );
 
+        let circle_style = CircleStyleUniform::default();
+
+        let circle_style_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Circle Style Buffer"),
+                contents: bytemuck::cast_slice(&[circle_style]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let shading = Shading::Flat;
+        let lighting_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Lighting Buffer"),
+                contents: bytemuck::cast_slice(&[LightingUniform::from(&shading)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
         let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
@@ -77,7 +164,27 @@ impl RenderState {
                         min_binding_size: None,
                     },
                     count: None,
-                }
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
             label: Some("uniform_bind_group_layout"),
         });
@@ -88,7 +195,15 @@ impl RenderState {
                 wgpu::BindGroupEntry {
                     binding: 0,
                     resource: uniform_buffer.as_entire_binding(),
-                }
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: circle_style_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: lighting_buffer.as_entire_binding(),
+                },
             ],
             label: Some("uniform_bind_group"),
         });
@@ -103,158 +218,759 @@ impl RenderState {
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc(), InstanceRaw::desc()],
-            },
-            fragment: Some(wgpu::FragmentState { 
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState { 
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, 
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None, 
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None, 
+        let sprites = SpriteBindings::new(&device, &queue);
+        let ball_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Ball Pipeline Layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, &sprites.bind_group_layout],
+            push_constant_ranges: &[],
         });
 
-        let mut ball = RenderCircle::new(32, [1.0, 0.0, 0.0]);
-
-        let vertices_vec = ball.get_vertices();
-        let vertices = vertices_vec.as_slice();
+        let srgb_output = (!target.format().describe().srgb).then(|| SrgbOutput::new(&device, target.format(), target.size()));
+        let color_format = srgb_output.as_ref().map_or(target.format(), |_| SrgbOutput::FORMAT);
 
-        let indices_vec = ball.get_indices();
-        let indices = indices_vec.as_slice();
+        let hdr_sample_counts = supported_sample_counts(adapter, &device, PostProcessor::HDR_FORMAT);
+        let supported_sample_counts = supported_sample_counts(adapter, &device, color_format);
+        let sample_count = 1;
 
-        let num_indices = indices.len() as u32;
+        let (render_pipeline, sdf_pipeline) = create_ball_pipelines(&device, &ball_pipeline_layout, &shader, (color_format, sample_count));
+        let (line_pipeline, hud_pipeline) = create_overlay_pipelines(&device, &render_pipeline_layout, &shader, (color_format, sample_count));
+        let scenery_pipeline = create_scenery_pipeline(&device, &render_pipeline_layout, &shader, (color_format, sample_count));
 
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
+        let mut ball = RenderCircle::new(32, [1.0, 0.0, 0.0]);
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(indices),
-                usage: wgpu::BufferUsages::INDEX,
-            }
-        );
+        let circle_mesh = Mesh::new(&device, "Circle", &ball.get_vertices(), &ball.get_indices());
+        let quad_mesh = Mesh::new(&device, "Quad", &RenderQuad::get_vertices([1.0, 0.0, 0.0]), &RenderQuad::get_indices());
 
         let instances = Vec::new();
-
-        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
-        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Instance Buffer"),
-            contents: bytemuck::cast_slice(&instance_data),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+        let instance_buffer = GrowableBuffer::new(&device, "Instance Buffer", wgpu::BufferUsages::VERTEX);
+        let line_buffer = GrowableBuffer::new(&device, "Debug Line Buffer", wgpu::BufferUsages::VERTEX);
+        let scenery_buffer = GrowableBuffer::new(&device, "Scenery Buffer", wgpu::BufferUsages::VERTEX);
+        let hud_buffer = GrowableBuffer::new(&device, "HUD Buffer", wgpu::BufferUsages::VERTEX);
 
         Self {
-            surface,
+            target,
+            srgb_output,
             device,
             queue,
-            config,
             render_pipeline,
-            vertex_buffer,
-            index_buffer,
+            sdf_pipeline,
+            shader,
+            render_pipeline_layout,
+            ball_pipeline_layout,
+            sprites,
+            line_pipeline,
+            scenery_pipeline,
+            hud_pipeline,
+            circle_mesh,
+            quad_mesh,
+            circle_mode: CircleMode::Sdf,
             instance_buffer,
+            dirty_instances: None,
             instances,
-            viewport_size_uniform: uniform,
+            ghost_instances: Vec::new(),
+            layer_order: false,
+            camera,
+            camera_uniform: uniform,
             uniform_buffer,
+            circle_style,
+            circle_style_buffer,
+            shading,
+            lighting_buffer,
             uniform_bind_group,
+            line_buffer,
+            line_vertex_count: 0,
+            scenery_buffer,
+            scenery_vertex_count: 0,
+            hud_buffer,
+            hud_vertex_count: 0,
+            trails: None,
+            post: None,
+            capture_target: None,
+            sample_count,
+            supported_sample_counts,
+            hdr_sample_counts,
+            msaa_scene: None,
+            msaa_target: None,
             size,
-            num_indices,
         }
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.viewport_size_uniform.viewport_size = new_size.into();
-            self.surface.configure(&self.device, &self.config);
+            self.camera.viewport = Vec2::new(new_size.width as f32, new_size.height as f32);
+            self.target.resize(&self.device, new_size.width, new_size.height);
+            if let Some(srgb_output) = self.srgb_output.as_mut() {
+                srgb_output.resize(&self.device, new_size.width, new_size.height);
+            }
+            if let Some(trails) = self.trails.as_mut() {
+                trails.resize(&self.device, new_size.width, new_size.height);
+            }
+            if let Some(post) = self.post.as_mut() {
+                post.resize(&self.device, new_size.width, new_size.height);
+            }
+            self.create_msaa_targets();
         }
     }
 
+    /// Presents frames with `mode`, or Fifo if the surface doesn't support it. Returns the
+    /// mode actually used, or `None` when drawing offscreen.
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> Option<wgpu::PresentMode> {
+        if !self.target.set_present_mode(&self.device, mode) {
+            self.target.set_present_mode(&self.device, wgpu::PresentMode::Fifo);
+        }
+        self.target.present_mode()
+    }
+
+    pub fn present_mode(&self) -> Option<wgpu::PresentMode> {
+        self.target.present_mode()
+    }
+
+    pub fn supported_present_modes(&self) -> &[wgpu::PresentMode] {
+        self.target.present_modes()
+    }
+
+    /// Draws with `count` samples per pixel, or the most the adapter supports below that.
+    /// Returns the count actually used.
+    pub fn set_sample_count(&mut self, count: u32) -> u32 {
+        let count = highest_sample_count(&self.supported_sample_counts, count);
+        if count == self.sample_count { return count }
+
+        self.sample_count = count;
+        self.create_pipelines();
+        if let Some(post) = self.post.take() {
+            let effects = post.effects().to_vec();
+            self.post = Some(PostProcessor::new(&self.device, (self.color_format(), count), self.target.size(), effects));
+        }
+        self.set_trails(None);
+        self.set_trails(self.trail_length());
+        self.create_msaa_targets();
+
+        count
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn supported_sample_counts(&self) -> &[u32] {
+        &self.supported_sample_counts
+    }
+
+    /// Samples per pixel the balls are drawn with, which can be fewer than `sample_count`
+    /// when the HDR scene texture doesn't support as many.
+    fn scene_sample_count(&self) -> u32 {
+        match self.post {
+            Some(_) => self.hdr_sample_count(),
+            None => self.sample_count,
+        }
+    }
+
+    fn hdr_sample_count(&self) -> u32 {
+        highest_sample_count(&self.hdr_sample_counts, self.sample_count)
+    }
+
+    /// Format and sample count the balls are drawn with, which is the trails' when they're on.
+    fn ball_target(&self) -> (wgpu::TextureFormat, u32) {
+        match self.trails {
+            Some(_) => (Trails::FORMAT, self.hdr_sample_count()),
+            None => (self.scene_format(), self.scene_sample_count()),
+        }
+    }
+
+    /// Recreates the pipelines that draw into the trails, the scene or the target, for a
+    /// new format or sample count.
+    fn create_pipelines(&mut self) {
+        let balls = self.ball_target();
+        (self.render_pipeline, self.sdf_pipeline) = create_ball_pipelines(&self.device, &self.ball_pipeline_layout, &self.shader, balls);
+        let scene = (self.scene_format(), self.scene_sample_count());
+        self.scenery_pipeline = create_scenery_pipeline(&self.device, &self.render_pipeline_layout, &self.shader, scene);
+        let target = (self.color_format(), self.sample_count);
+        (self.line_pipeline, self.hud_pipeline) = create_overlay_pipelines(&self.device, &self.render_pipeline_layout, &self.shader, target);
+    }
+
+    fn create_msaa_targets(&mut self) {
+        let size = self.target.size();
+        self.msaa_target = multisampled_view(&self.device, self.color_format(), size, self.sample_count);
+        self.msaa_scene = match self.post {
+            Some(_) => multisampled_view(&self.device, PostProcessor::HDR_FORMAT, size, self.scene_sample_count()),
+            None => None,
+        };
+    }
+
+    /// Leaves trails that take `length` frames to fade, or turns them off with `None`.
+    /// Lengths are clamped to [`Trails::MAX_LENGTH`].
+    pub fn set_trails(&mut self, length: Option<u32>) {
+        let had_trails = self.trails.is_some();
+        match (length, self.trails.as_mut()) {
+            (None, _) => self.trails = None,
+            (Some(length), Some(trails)) => trails.length = length.clamp(1, Trails::MAX_LENGTH),
+            (Some(length), None) => {
+                let output = (self.scene_format(), self.scene_sample_count());
+                self.trails = Some(Trails::new(&self.device, output, self.target.size(), length, self.hdr_sample_count()));
+            }
+        }
+
+        if self.trails.is_some() != had_trails {
+            self.create_pipelines();
+        }
+    }
+
+    pub fn trail_length(&self) -> Option<u32> {
+        self.trails.as_ref().map(|trails| trails.length)
+    }
+
+    /// Runs `effects` in order on the way to the screen. An empty chain turns
+    /// post-processing off and draws straight to the target again.
+    pub fn set_post_effects(&mut self, effects: Vec<PostEffect>) {
+        let old_format = self.scene_format();
+
+        match (effects.is_empty(), self.post.as_mut()) {
+            (true, _) => self.post = None,
+            (false, Some(post)) => post.set_effects(&self.device, effects),
+            (false, None) => {
+                let output = (self.color_format(), self.sample_count);
+                self.post = Some(PostProcessor::new(&self.device, output, self.target.size(), effects));
+            }
+        }
+
+        if self.scene_format() != old_format {
+            self.create_pipelines();
+            self.set_trails(None);
+            self.set_trails(self.trail_length());
+            self.create_msaa_targets();
+        }
+    }
+
+    pub fn post_effects(&self) -> &[PostEffect] {
+        self.post.as_ref().map_or(&[], |post| post.effects())
+    }
+
+    /// Format the balls are drawn in, before any post-processing.
+    fn scene_format(&self) -> wgpu::TextureFormat {
+        match self.post {
+            Some(_) => PostProcessor::HDR_FORMAT,
+            None => self.color_format(),
+        }
+    }
+
+    /// Format frames are drawn in, always one that encodes sRGB.
+    fn color_format(&self) -> wgpu::TextureFormat {
+        match self.srgb_output {
+            Some(_) => SrgbOutput::FORMAT,
+            None => self.target.format(),
+        }
+    }
+
+    /// Replaces the sprites balls can be drawn with, see [`Instance::sprite`].
+    pub fn set_sprite_atlas(&mut self, atlas: &SpriteAtlas) {
+        self.sprites.set_atlas(&self.device, &self.queue, atlas);
+    }
+
+    /// Outline drawn around SDF circles, `width` is in pixels. `0.0` turns it off.
+    pub fn set_outline(&mut self, width: f32, color: [f32; 4]) {
+        self.circle_style = CircleStyleUniform::new(width, color);
+        self.queue.write_buffer(&self.circle_style_buffer, 0, bytemuck::cast_slice(&[self.circle_style]));
+    }
+
+    pub fn outline_width(&self) -> f32 {
+        self.circle_style.outline_width
+    }
+
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.shading = shading;
+        self.queue.write_buffer(&self.lighting_buffer, 0, bytemuck::cast_slice(&[LightingUniform::from(&shading)]));
+    }
+
+    pub fn shading(&self) -> Shading {
+        self.shading
+    }
+
     pub fn input(&mut self, _event: &WindowEvent) -> bool {
         false
     }
 
     pub fn add_instance(&mut self, instance: Instance) {
         self.instances.push(instance);
-        self.recreate_instance_buffer();
+        let len = self.instances.len();
+        self.mark_instances_dirty(len - 1..len);
     }
 
-    pub fn recreate_instance_buffer(&mut self) {
-        let instance_data = self.instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+    /// Replaces the instance at `index`, only scheduling an upload if it actually changed.
+    pub fn update_instance(&mut self, index: usize, instance: Instance) {
+        if self.instances[index] == instance { return }
+        self.instances[index] = instance;
+        self.mark_instances_dirty(index..index + 1);
+    }
+
+    pub fn clear_instances(&mut self) {
+        self.instances.clear();
+        self.ghost_instances.clear();
+        self.dirty_instances = None;
+    }
+
+    /// Removes the instance at `index`, moving the last one into its place like
+    /// [`Physics::remove_ball`](crate::physics::Physics::remove_ball).
+    pub fn swap_remove_instance(&mut self, index: usize) {
+        self.instances.swap_remove(index);
+        if index < self.instances.len() {
+            self.mark_instances_dirty(index..index + 1);
+        }
+    }
+
+    /// Replaces the ghosts drawn along with the balls.
+    pub fn set_ghosts(&mut self, ghosts: &[Instance]) {
+        self.ghost_instances.clear();
+        self.ghost_instances.extend_from_slice(ghosts);
+    }
 
-        self.instance_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Instance Buffer"),
-            contents: bytemuck::cast_slice(&instance_data),
-            usage: wgpu::BufferUsages::VERTEX,
+    pub fn set_layer_order(&mut self, layer_order: bool) {
+        self.layer_order = layer_order;
+        self.mark_instances_dirty(0..self.instances.len());
+    }
+
+    pub fn layer_order(&self) -> bool {
+        self.layer_order
+    }
+
+    /// Moves the instance at `order[i]` to index `i`, mirroring [`Physics::reorder`](crate::physics::Physics::reorder).
+    pub fn reorder_instances(&mut self, order: &[usize]) {
+        self.instances = order.iter().map(|&i| self.instances[i]).collect();
+        self.mark_instances_dirty(0..self.instances.len());
+    }
+
+    /// Schedules `range` of `instances` to be uploaded before the next draw.
+    pub fn mark_instances_dirty(&mut self, range: Range<usize>) {
+        self.dirty_instances = Some(match self.dirty_instances.take() {
+            Some(dirty) => dirty.start.min(range.start)..dirty.end.max(range.end),
+            None => range,
         });
     }
 
+    /// Uploads every instance that changed and all the ghosts, growing the buffer if they no
+    /// longer fit. In layer order everything is sorted and uploaded again.
+    pub fn upload_instances(&mut self) {
+        let stride = std::mem::size_of::<InstanceRaw>();
+        let count = self.instances.len() + self.ghost_instances.len();
+
+        if self.instance_buffer.reserve(&self.device, (count * stride) as wgpu::BufferAddress) {
+            self.dirty_instances = Some(0..self.instances.len());
+        }
+
+        if self.layer_order {
+            if count == 0 { return }
+            // Sorting is stable, so the order within a layer stays the same
+            let mut ordered = self.instances.iter().chain(self.ghost_instances.iter()).collect::<Vec<_>>();
+            ordered.sort_by(|a, b| a.layer.total_cmp(&b.layer));
+            let instance_data = ordered.into_iter().map(Instance::to_raw).collect::<Vec<_>>();
+            self.instance_buffer.write(&self.queue, 0, bytemuck::cast_slice(&instance_data));
+            return;
+        }
+
+        if !self.ghost_instances.is_empty() {
+            let ghost_data = self.ghost_instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+            self.instance_buffer.write(&self.queue, (self.instances.len() * stride) as wgpu::BufferAddress, bytemuck::cast_slice(&ghost_data));
+        }
+
+        let Some(dirty) = self.dirty_instances.take() else { return };
+        let dirty = dirty.start..dirty.end.min(self.instances.len());
+        if dirty.is_empty() { return }
+
+        let instance_data = self.instances[dirty.clone()].iter().map(Instance::to_raw).collect::<Vec<_>>();
+        self.instance_buffer.write(&self.queue, (dirty.start * stride) as wgpu::BufferAddress, bytemuck::cast_slice(&instance_data));
+    }
+
+    /// Replaces the debug lines drawn over the balls, as pairs of world space vertices.
+    pub fn set_debug_lines(&mut self, vertices: &[Vertex]) {
+        self.line_vertex_count = upload_vertices(&self.device, &self.queue, &mut self.line_buffer, vertices);
+    }
+
+    /// Replaces the boundary and obstacles, as triangles in world space drawn under the balls.
+    pub fn set_scenery(&mut self, vertices: &[Vertex]) {
+        self.scenery_vertex_count = upload_vertices(&self.device, &self.queue, &mut self.scenery_buffer, vertices);
+    }
+
+    /// Replaces the HUD, as triangles in screen pixels.
+    pub fn set_hud(&mut self, vertices: &[Vertex]) {
+        self.hud_vertex_count = upload_vertices(&self.device, &self.queue, &mut self.hud_buffer, vertices);
+    }
+
+    fn upload_camera(&mut self) {
+        let uniform = self.camera.to_uniform();
+        if uniform == self.camera_uniform { return }
+
+        self.camera_uniform = uniform;
+        self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.upload_instances();
+        self.upload_camera();
+
+        let frame = self.target.acquire()?;
+        self.draw_trails();
+        match self.srgb_output.as_ref() {
+            Some(srgb_output) => {
+                self.draw(srgb_output.view(), true);
+                srgb_output.encode(&self.device, &self.queue, &frame.view);
+            }
+            None => self.draw(&frame.view, true),
+        }
+        frame.present();
+
+        Ok(())
+    }
+
+    /// Fades the trails and adds this frame's balls to them.
+    fn draw_trails(&self) {
+        let Some(trails) = self.trails.as_ref() else { return };
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Trail Encoder"),
+        });
+        {
+            let mut render_pass = trails.begin_pass(&mut encoder);
+            draw_balls(&mut render_pass, self);
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Draws a frame into `view`, with the debug lines and HUD on top if `overlays` is set.
+    fn draw(&self, view: &wgpu::TextureView, overlays: bool) {
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
 
+        let (scene_view, msaa_scene) = match self.post.as_ref() {
+            Some(post) => (post.scene_view(), self.msaa_scene.as_ref()),
+            None => (view, self.msaa_target.as_ref()),
+        };
         {
+            let clear = wgpu::LoadOp::Clear(wgpu::Color {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            });
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
+                color_attachments: &[Some(color_attachment(msaa_scene, scene_view, clear))],
                 depth_stencil_attachment: None,
             });
 
-            render_pass.set_pipeline(&self.render_pipeline); 
+            draw_scenery(&mut render_pass, self);
+            match self.trails.as_ref() {
+                Some(trails) => trails.draw(&mut render_pass),
+                None => draw_balls(&mut render_pass, self),
+            }
 
-            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            if overlays && self.post.is_none() {
+                self.draw_overlays(&mut render_pass);
+            }
+        }
+
+        // Overlays go on top of post-processing so they stay sharp
+        if let Some(post) = self.post.as_ref() {
+            let output = post.run(&self.device, &self.queue, &mut encoder);
 
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as u32); 
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay Pass"),
+                color_attachments: &[Some(color_attachment(self.msaa_target.as_ref(), view, wgpu::LoadOp::Clear(wgpu::Color::BLACK)))],
+                depth_stencil_attachment: None,
+            });
+
+            post.draw_output(&mut render_pass, &output);
+            if overlays {
+                self.draw_overlays(&mut render_pass);
+            }
         }
-    
+
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-    
-        Ok(())
     }
-}
\ No newline at end of file
+
+    /// Debug lines and the HUD.
+    fn draw_overlays<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        if self.line_vertex_count > 0 {
+            render_pass.set_pipeline(&self.line_pipeline);
+            render_pass.set_vertex_buffer(0, self.line_buffer.buffer().slice(..));
+            render_pass.draw(0..self.line_vertex_count, 0..1);
+        }
+
+        if self.hud_vertex_count > 0 {
+            render_pass.set_pipeline(&self.hud_pipeline);
+            render_pass.set_vertex_buffer(0, self.hud_buffer.buffer().slice(..));
+            render_pass.draw(0..self.hud_vertex_count, 0..1);
+        }
+    }
+
+    /// Reads the last rendered frame back. Windows can't be read directly, so the scene is
+    /// drawn again into an offscreen texture in the format it's drawn in, without the
+    /// debug lines and HUD.
+    pub fn capture(&mut self) -> Option<FrameImage> {
+        let (width, height) = self.target.size();
+        if width == 0 || height == 0 { return None }
+
+        let target = match self.target.texture() {
+            Some(_) => &self.target,
+            None => {
+                if self.capture_target.as_ref().is_none_or(|target| target.size() != (width, height)) {
+                    self.capture_target = Some(RenderTarget::offscreen(&self.device, self.color_format(), width, height));
+                }
+
+                let target = self.capture_target.as_ref()?;
+                self.draw(&target.acquire().ok()?.view, false);
+                target
+            }
+        };
+
+        Some(capture::read_texture(&self.device, &self.queue, target.texture()?, target.format(), width, height))
+    }
+}
+
+/// The modes of [`PRESENT_MODES`] `surface` supports. Fifo is always supported.
+fn supported_present_modes(surface: &wgpu::Surface, adapter: &wgpu::Adapter) -> Vec<wgpu::PresentMode> {
+    let supported = surface.get_supported_modes(adapter);
+    PRESENT_MODES.into_iter()
+        .filter(|mode| *mode == wgpu::PresentMode::Fifo || supported.contains(mode))
+        .collect()
+}
+
+/// Finds an adapter, falling back to a software one if there's nothing else.
+pub async fn request_adapter(instance: &wgpu::Instance, compatible_surface: Option<&wgpu::Surface>) -> Option<wgpu::Adapter> {
+    for force_fallback_adapter in [false, true] {
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface,
+                force_fallback_adapter,
+            },
+        ).await;
+
+        if adapter.is_some() {
+            return adapter;
+        }
+    }
+
+    None
+}
+
+pub async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            // So multisampling is only used on formats the adapter really supports it for, see `supported_sample_counts`
+            features: adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            limits: wgpu::Limits::default(),
+            label: None,
+        },
+        None, // Trace path
+    ).await.unwrap()
+}
+
+/// Sample counts that can be picked with [`RenderState::set_sample_count`].
+pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+/// The counts in [`SAMPLE_COUNTS`] that `format` can be drawn and resolved with.
+///
+/// wgpu only says whether a format can be multisampled at all, not with how many samples,
+/// and its render passes only take 1 or 4 (the count WebGPU guarantees), so 2 and 8 are never
+/// offered and fall back to a lower count.
+fn supported_sample_counts(adapter: &wgpu::Adapter, device: &wgpu::Device, format: wgpu::TextureFormat) -> Vec<u32> {
+    let features = match device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+        true => adapter.get_texture_format_features(format),
+        false => format.describe().guaranteed_format_features,
+    };
+    let multisampled = features.flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE | wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+
+    SAMPLE_COUNTS.into_iter()
+        .filter(|&count| count == 1 || (count == 4 && multisampled))
+        .collect()
+}
+
+/// The highest of `supported` that's no more than `count`.
+fn highest_sample_count(supported: &[u32], count: u32) -> u32 {
+    supported.iter().copied().filter(|&supported| supported <= count).max().unwrap_or(1)
+}
+
+fn create_circle_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    (format, sample_count): (wgpu::TextureFormat, u32),
+    label: &str,
+    (vs_entry, fs_entry): (&str, &str),
+    blend: wgpu::BlendState,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: vs_entry,
+            buffers: &[Vertex::desc(), InstanceRaw::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fs_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+        multiview: None,
+    })
+}
+
+/// Mesh and SDF circle pipelines drawing into `target`, a format and sample count.
+fn create_ball_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    target: (wgpu::TextureFormat, u32),
+) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+    let render_pipeline = create_circle_pipeline(
+        device,
+        layout,
+        shader,
+        target,
+        "Render Pipeline",
+        ("vs_main", "fs_ball"),
+        wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+    );
+
+    let sdf_pipeline = create_circle_pipeline(
+        device,
+        layout,
+        shader,
+        target,
+        "SDF Render Pipeline",
+        ("vs_sdf", "fs_sdf"),
+        wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+    );
+
+    (render_pipeline, sdf_pipeline)
+}
+
+fn draw_balls<'a>(render_pass: &mut wgpu::RenderPass<'a>, state: &'a RenderState) {
+    let (pipeline, mesh) = match state.circle_mode {
+        CircleMode::Mesh => (&state.render_pipeline, &state.circle_mesh),
+        CircleMode::Sdf => (&state.sdf_pipeline, &state.quad_mesh),
+    };
+
+    render_pass.set_pipeline(pipeline);
+
+    render_pass.set_bind_group(0, &state.uniform_bind_group, &[]);
+    render_pass.set_bind_group(1, &state.sprites.bind_group, &[]);
+    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+    render_pass.set_vertex_buffer(1, state.instance_buffer.buffer().slice(..));
+    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+    let count = state.instances.len() + state.ghost_instances.len();
+    render_pass.draw_indexed(0..mesh.num_indices, 0, 0..count as u32);
+}
+
+fn draw_scenery<'a>(render_pass: &mut wgpu::RenderPass<'a>, state: &'a RenderState) {
+    if state.scenery_vertex_count == 0 { return }
+
+    render_pass.set_pipeline(&state.scenery_pipeline);
+    render_pass.set_bind_group(0, &state.uniform_bind_group, &[]);
+    render_pass.set_vertex_buffer(0, state.scenery_buffer.buffer().slice(..));
+    render_pass.draw(0..state.scenery_vertex_count, 0..1);
+}
+
+/// Writes `vertices` to the start of `buffer`, returning how many there are.
+fn upload_vertices(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &mut GrowableBuffer, vertices: &[Vertex]) -> u32 {
+    let data: &[u8] = bytemuck::cast_slice(vertices);
+    buffer.reserve(device, data.len() as wgpu::BufferAddress);
+    buffer.write(queue, 0, data);
+    vertices.len() as u32
+}
+
+/// Debug line and HUD pipelines drawing into `target`, a format and sample count.
+fn create_overlay_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    target: (wgpu::TextureFormat, u32),
+) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+    let line_pipeline = create_overlay_pipeline(
+        device,
+        layout,
+        shader,
+        target,
+        "Debug Line Pipeline",
+        "vs_line",
+        wgpu::PrimitiveTopology::LineList,
+    );
+
+    let hud_pipeline = create_overlay_pipeline(
+        device,
+        layout,
+        shader,
+        target,
+        "HUD Pipeline",
+        "vs_hud",
+        wgpu::PrimitiveTopology::TriangleList,
+    );
+
+    (line_pipeline, hud_pipeline)
+}
+
+/// Boundary and obstacle pipeline drawing into `scene`, a format and sample count.
+fn create_scenery_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    scene: (wgpu::TextureFormat, u32),
+) -> wgpu::RenderPipeline {
+    create_overlay_pipeline(device, layout, shader, scene, "Scenery Pipeline", "vs_line", wgpu::PrimitiveTopology::TriangleList)
+}
+
+/// A pipeline for plain, non-instanced [`Vertex`]es, like the overlays drawn over the balls.
+fn create_overlay_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    (format, sample_count): (wgpu::TextureFormat, u32),
+    label: &str,
+    vs_entry: &str,
+    topology: wgpu::PrimitiveTopology,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: vs_entry,
+            buffers: &[Vertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology,
+            ..Default::default()
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+        multiview: None,
+    })
+}
@@ -0,0 +1,173 @@
+/// Where a [`RenderState`](crate::render_state::RenderState) draws its frames.
+pub enum RenderTarget {
+    /// A window's swapchain.
+    Surface {
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+        /// Present modes the surface supports, Fifo always among them.
+        present_modes: Vec<wgpu::PresentMode>,
+    },
+    /// A texture that can be read back, for running without a window.
+    Offscreen {
+        texture: wgpu::Texture,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// The texture one frame is drawn into.
+pub struct TargetFrame {
+    surface_texture: Option<wgpu::SurfaceTexture>,
+    pub(crate) view: wgpu::TextureView,
+}
+
+impl RenderTarget {
+    pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn offscreen(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        Self::Offscreen {
+            texture: create_offscreen_texture(device, format, width, height),
+            format,
+            width,
+            height,
+        }
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            RenderTarget::Surface { config, .. } => config.format,
+            RenderTarget::Offscreen { format, .. } => *format,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, new_width: u32, new_height: u32) {
+        match self {
+            RenderTarget::Surface { surface, config, .. } => {
+                config.width = new_width;
+                config.height = new_height;
+                surface.configure(device, config);
+            }
+            RenderTarget::Offscreen { texture, format, width, height } => {
+                *texture = create_offscreen_texture(device, *format, new_width, new_height);
+                *width = new_width;
+                *height = new_height;
+            }
+        }
+    }
+
+    /// Present modes the target can switch between, none if it isn't a window.
+    pub fn present_modes(&self) -> &[wgpu::PresentMode] {
+        match self {
+            RenderTarget::Surface { present_modes, .. } => present_modes,
+            RenderTarget::Offscreen { .. } => &[],
+        }
+    }
+
+    pub fn present_mode(&self) -> Option<wgpu::PresentMode> {
+        match self {
+            RenderTarget::Surface { config, .. } => Some(config.present_mode),
+            RenderTarget::Offscreen { .. } => None,
+        }
+    }
+
+    /// Reconfigures the surface to present with `mode`. Does nothing and returns `false` if
+    /// the surface doesn't support it.
+    pub fn set_present_mode(&mut self, device: &wgpu::Device, mode: wgpu::PresentMode) -> bool {
+        match self {
+            RenderTarget::Surface { surface, config, present_modes } if present_modes.contains(&mode) => {
+                config.present_mode = mode;
+                surface.configure(device, config);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn acquire(&self) -> Result<TargetFrame, wgpu::SurfaceError> {
+        match self {
+            RenderTarget::Surface { surface, .. } => {
+                let output = surface.get_current_texture()?;
+                let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(TargetFrame { surface_texture: Some(output), view })
+            }
+            RenderTarget::Offscreen { texture, .. } => {
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(TargetFrame { surface_texture: None, view })
+            }
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            RenderTarget::Surface { config, .. } => (config.width, config.height),
+            RenderTarget::Offscreen { width, height, .. } => (*width, *height),
+        }
+    }
+
+    /// The texture frames end up in, if it can be read back.
+    pub fn texture(&self) -> Option<&wgpu::Texture> {
+        match self {
+            RenderTarget::Surface { .. } => None,
+            RenderTarget::Offscreen { texture, .. } => Some(texture),
+        }
+    }
+}
+
+impl TargetFrame {
+    /// Shows the frame if it belongs to a window.
+    pub fn present(self) {
+        if let Some(surface_texture) = self.surface_texture {
+            surface_texture.present();
+        }
+    }
+}
+
+fn create_offscreen_texture(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Target"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    })
+}
+
+/// A texture to draw into with `sample_count` samples and resolve into a regular target of
+/// the same size and format. `None` when there's nothing to resolve, at one sample.
+pub fn multisampled_view(device: &wgpu::Device, format: wgpu::TextureFormat, (width, height): (u32, u32), sample_count: u32) -> Option<wgpu::TextureView> {
+    if sample_count <= 1 { return None }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Multisampled Target"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
+
+/// Draws into `multisampled` and resolves into `view` if there is one, otherwise straight into `view`.
+pub fn color_attachment<'a>(
+    multisampled: Option<&'a wgpu::TextureView>,
+    view: &'a wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) -> wgpu::RenderPassColorAttachment<'a> {
+    match multisampled {
+        Some(multisampled) => wgpu::RenderPassColorAttachment {
+            view: multisampled,
+            resolve_target: Some(view),
+            ops: wgpu::Operations { load, store: true },
+        },
+        None => wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations { load, store: true },
+        },
+    }
+}
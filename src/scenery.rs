@@ -0,0 +1,145 @@
+use crate::{vertex::Vertex, util::{Color, Vec2}, physics::{Physics, Boundary, Obstacle}};
+
+/// How the boundary and obstacles are drawn.
+#[derive(Debug, Clone, Copy)]
+pub struct SceneryStyle {
+    /// Inside the boundary, behind the balls.
+    pub(crate) interior: Color,
+    pub(crate) outline: Color,
+    /// Inside circle obstacles.
+    pub(crate) obstacle: Color,
+    /// In world units. Outlines are drawn just outside the boundary and just inside circle
+    /// obstacles, so balls touch them where they stop. Segments have no thickness, so
+    /// they're drawn as hairlines instead.
+    pub(crate) outline_width: f32,
+}
+
+impl Default for SceneryStyle {
+    fn default() -> Self {
+        Self {
+            interior: Color::from_hex(0x15171d),
+            outline: Color::from_hex(0x8a93a6),
+            obstacle: Color::from_hex(0x2e3440),
+            outline_width: 4.0,
+        }
+    }
+}
+
+impl SceneryStyle {
+    /// Reads `INTERIOR,OUTLINE,OBSTACLE` as hex colors like `#15171d`.
+    pub fn with_colors(self, colors: &str) -> Option<Self> {
+        let colors = colors.split(',').map(Color::parse_hex).collect::<Option<Vec<_>>>()?;
+        let [interior, outline, obstacle] = colors[..] else { return None };
+        Some(Self { interior, outline, obstacle, ..self })
+    }
+}
+
+/// Collects world space triangles for the static parts of the simulation, to be drawn
+/// under the balls.
+#[derive(Debug, Clone, Default)]
+pub struct Scenery {
+    pub(crate) vertices: Vec<Vertex>,
+}
+
+impl Scenery {
+    const CIRCLE_SEGMENTS: u32 = 128;
+
+    /// Adds the boundary and obstacles of `physics`, from the same shapes balls collide with.
+    /// `pixel` is the size of a screen pixel in world units.
+    pub fn physics(&mut self, physics: &Physics, style: &SceneryStyle, pixel: f32) {
+        match physics.boundary {
+            Boundary::None => {}
+            Boundary::Circle { center, radius } => {
+                self.ring(center, 0.0, radius, style.interior);
+                self.ring(center, radius, radius + style.outline_width, style.outline);
+            }
+        }
+
+        for obstacle in physics.obstacles.iter() {
+            match *obstacle {
+                Obstacle::Circle { center, radius } => {
+                    let inner = (radius - style.outline_width).max(0.0);
+                    self.ring(center, 0.0, inner, style.obstacle);
+                    self.ring(center, inner, radius, style.outline);
+                }
+                // Any wider and balls would look like they sink into it
+                Obstacle::Segment { start, end } => self.segment(start, end, pixel, style.outline),
+            }
+        }
+    }
+
+    fn triangle(&mut self, points: [Vec2; 3], color: Color) {
+        let color = color.into_arr();
+        self.vertices.extend(points.map(|point| Vertex { position: [point.x, point.y], color }));
+    }
+
+    /// The area between two circles, or a disc if `inner` is `0.0`.
+    pub fn ring(&mut self, center: Vec2, inner: f32, outer: f32, color: Color) {
+        if outer <= inner { return }
+
+        let point = |i: u32, radius: f32| {
+            let angle = i as f32 / Self::CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + Vec2::new(angle.cos(), angle.sin()) * radius
+        };
+
+        for i in 0..Self::CIRCLE_SEGMENTS {
+            let (outer_start, outer_end) = (point(i, outer), point(i + 1, outer));
+            match inner > 0.0 {
+                true => {
+                    let (inner_start, inner_end) = (point(i, inner), point(i + 1, inner));
+                    self.triangle([inner_start, outer_start, outer_end], color);
+                    self.triangle([inner_start, outer_end, inner_end], color);
+                }
+                false => self.triangle([center, outer_start, outer_end], color),
+            }
+        }
+    }
+
+    /// A line `width` wide with round ends.
+    pub fn segment(&mut self, start: Vec2, end: Vec2, width: f32, color: Color) {
+        let half = width / 2.0;
+        if (end - start).length() > 0.0 {
+            let side = (end - start).normalize().perp() * half;
+            self.triangle([start - side, start + side, end + side], color);
+            self.triangle([start - side, end + side, end - side], color);
+        }
+        self.ring(start, 0.0, half, color);
+        self.ring(end, 0.0, half, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_colors() {
+        let style = SceneryStyle { outline_width: 2.0, ..Default::default() };
+        let style = style.with_colors("#000000, ffffff,#808080").unwrap();
+        assert_eq!(style.interior.into_arr(), [0.0; 3]);
+        assert_eq!(style.outline.into_arr(), [1.0; 3]);
+        assert_eq!(style.obstacle.into_arr(), Color::from_hex(0x808080).into_arr());
+        assert_eq!(style.outline_width, 2.0);
+    }
+
+    #[test]
+    fn segments_are_a_pixel_wide() {
+        let mut physics = Physics::default();
+        physics.boundary = Boundary::None;
+        physics.add_obstacle(Obstacle::Segment { start: Vec2::new(-10.0, 3.0), end: Vec2::new(10.0, 3.0) });
+
+        let mut scenery = Scenery::default();
+        scenery.physics(&physics, &SceneryStyle::default(), 0.5);
+        let (min, max) = scenery.vertices.iter()
+            .map(|vertex| vertex.position[1])
+            .fold((f32::MAX, f32::MIN), |(min, max), y| (min.min(y), max.max(y)));
+        assert!((min - 2.75).abs() < 1e-5 && (max - 3.25).abs() < 1e-5, "{} {}", min, max);
+    }
+
+    #[test]
+    fn with_malformed_colors() {
+        for colors in ["", "#000000", "#000000,#ffffff", "#000000,#ffffff,#808080,#ff0000", "#000000,,#808080", "#000000,#ffffff,#80808"] {
+            assert!(SceneryStyle::default().with_colors(colors).is_none(), "{:?}", colors);
+        }
+    }
+}
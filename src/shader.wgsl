@@ -2,51 +2,234 @@
 
 struct VertexInput {
     @location(0) position: vec2<f32>,
+    // sRGB
     @location(1) color: vec3<f32>,
 };
 
 struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>,
-
-    @location(9) color: vec3<f32>,
+    @location(5) position: vec2<f32>,
+    // Radius and rotation in radians
+    @location(6) scale_rotation: vec2<f32>,
+    // sRGB with straight alpha
+    @location(7) color: vec4<f32>,
+    // Cell in the sprite atlas, or NO_SPRITE, in the low 16 bits and occlusion as 8 bits
+    // above it
+    @location(8) sprite_occlusion: u32,
 };
 
-struct VpSizeUniform {
+struct CameraUniform {
+    center: vec2<f32>,
     viewport_size: vec2<f32>,
+    zoom: f32,
+    rotation: f32,
+}
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct CircleStyleUniform {
+    // sRGB, with alpha as coverage
+    outline_color: vec4<f32>,
+    outline_width: f32,
 }
-@group(0) @binding(0) // 1.
-var<uniform> vp_size: VpSizeUniform;
+@group(0) @binding(1)
+var<uniform> circle_style: CircleStyleUniform;
+
+struct LightingUniform {
+    // Towards the light, screen space with y up
+    direction: vec3<f32>,
+    // 0 for flat shading
+    enabled: u32,
+    ambient: f32,
+    diffuse: f32,
+    specular: f32,
+    shininess: f32,
+    rim: f32,
+    rim_power: f32,
+    occlusion: f32,
+}
+@group(0) @binding(2)
+var<uniform> lighting: LightingUniform;
+
+let NO_SPRITE: u32 = 0xffffu;
+
+struct SpriteAtlasUniform {
+    // Columns and rows of cells
+    grid: vec2<f32>,
+}
+@group(1) @binding(0)
+var sprite_atlas: texture_2d<f32>;
+@group(1) @binding(1)
+var sprite_sampler: sampler;
+@group(1) @binding(2)
+var<uniform> sprite_grid: SpriteAtlasUniform;
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
+    // Linear
     @location(0) color: vec3<f32>,
+    // Position inside the unit circle, before the ball's rotation
+    @location(1) local: vec2<f32>,
+    @location(2) @interpolate(flat) sprite: u32,
+    // `local` as it's turned on screen, for lighting
+    @location(3) facing: vec2<f32>,
+    @location(4) occlusion: f32,
+    @location(5) alpha: f32,
 };
 
+// Colors come in as sRGB and are shaded and blended in linear light. The target's sRGB
+// format, or the pass that copies into the surface, encodes them again.
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+fn rotate(v: vec2<f32>, angle: f32) -> vec2<f32> {
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec2<f32>(v.x * c - v.y * s, v.x * s + v.y * c);
+}
+
+// World space to clip space
+fn world_to_clip(world: vec2<f32>) -> vec4<f32> {
+    let pixel = rotate(world - camera.center, -camera.rotation) * camera.zoom;
+    let clip = pixel / (camera.viewport_size / 2.0) * vec2<f32>(1.0, -1.0);
+    return vec4<f32>(clip, 0.0, 1.0);
+}
+
+// Rotates, scales and moves a point from the unit circle into the world, then to clip space
+fn to_clip(instance: InstanceInput, local: vec2<f32>) -> vec4<f32> {
+    return world_to_clip(rotate(local, instance.scale_rotation.y) * instance.scale_rotation.x + instance.position);
+}
+
 @vertex
 fn vs_main(
     model: VertexInput,
     instance: InstanceInput,
 ) -> VertexOutput {
-    let model_matrix = mat4x4<f32>(
-        instance.model_matrix_0,
-        instance.model_matrix_1,
-        instance.model_matrix_2,
-        instance.model_matrix_3,
-    );
+    var out: VertexOutput;
+    out.color = srgb_to_linear(instance.color.rgb);
+    out.alpha = instance.color.a;
+    out.local = model.position;
+    out.sprite = instance.sprite_occlusion & 0xffffu;
+    out.facing = rotate(model.position, instance.scale_rotation.y - camera.rotation);
+    out.occlusion = f32((instance.sprite_occlusion >> 16u) & 0xffu) / 255.0;
+    out.clip_position = to_clip(instance, model.position);
+    return out;
+}
+
+@vertex
+fn vs_sdf(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    // Grow the quad by a couple of pixels so the anti-aliased edge isn't cut off
+    let local = model.position * (1.0 + 2.0 / max(instance.scale_rotation.x * camera.zoom, 0.001));
+
+    var out: VertexOutput;
+    out.color = srgb_to_linear(instance.color.rgb);
+    out.alpha = instance.color.a;
+    out.local = local;
+    out.sprite = instance.sprite_occlusion & 0xffffu;
+    out.facing = rotate(local, instance.scale_rotation.y - camera.rotation);
+    out.occlusion = f32((instance.sprite_occlusion >> 16u) & 0xffu) / 255.0;
+    out.clip_position = to_clip(instance, local);
+    return out;
+}
 
+// Debug lines, already in world space
+@vertex
+fn vs_line(model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    out.color = instance.color * instance.color;
-    out.clip_position = ((model_matrix * vec4<f32>(model.position, 0.0, 1.0)) / vec4<f32>((vp_size.viewport_size / 2.0), 1.0, 1.0))
-         * vec4<f32>(1.0, -1.0, 0.0, 1.0) + vec4<f32>(-1.0, 1.0, 1.0, 0.0);
+    out.color = srgb_to_linear(model.color);
+    out.local = vec2<f32>(0.0, 0.0);
+    out.sprite = NO_SPRITE;
+    out.facing = vec2<f32>(0.0, 0.0);
+    out.occlusion = 0.0;
+    out.alpha = 1.0;
+    out.clip_position = world_to_clip(model.position);
+    return out;
+}
+
+// HUD, in screen pixels from the top left
+@vertex
+fn vs_hud(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.color = srgb_to_linear(model.color);
+    out.local = vec2<f32>(0.0, 0.0);
+    out.sprite = NO_SPRITE;
+    out.facing = vec2<f32>(0.0, 0.0);
+    out.occlusion = 0.0;
+    out.alpha = 1.0;
+    let clip = (model.position / camera.viewport_size * 2.0 - 1.0) * vec2<f32>(1.0, -1.0);
+    out.clip_position = vec4<f32>(clip, 0.0, 1.0);
     return out;
 }
 
 // Fragment shader
 
+// Lights `color` as a point on a sphere, or leaves it alone with flat shading
+fn shade(color: vec3<f32>, in: VertexOutput) -> vec3<f32> {
+    if (lighting.enabled == 0u) {
+        return color;
+    }
+
+    // Flip to y up, the edge of the circle is where the sphere's side faces the screen
+    let xy = vec2<f32>(in.facing.x, -in.facing.y);
+    let normal = vec3<f32>(xy, sqrt(max(1.0 - dot(xy, xy), 0.0)));
+    let view = vec3<f32>(0.0, 0.0, 1.0);
+
+    let diffuse = max(dot(normal, lighting.direction), 0.0) * lighting.diffuse;
+    let half_vector = normalize(lighting.direction + view);
+    let specular = pow(max(dot(normal, half_vector), 0.0), lighting.shininess) * lighting.specular;
+    let rim = pow(1.0 - normal.z, lighting.rim_power) * lighting.rim;
+    let occlusion = 1.0 - in.occlusion * lighting.occlusion;
+
+    return (color * (lighting.ambient + diffuse) + specular + rim) * occlusion;
+}
+
+// The ball's color with its sprite, if it has one, drawn over it
+fn ball_color(in: VertexOutput) -> vec3<f32> {
+    let cell = vec2<f32>(f32(in.sprite % u32(sprite_grid.grid.x)), f32(in.sprite / u32(sprite_grid.grid.x)));
+    // Stay half a texel inside the cell so neighbours don't bleed in
+    let margin = 0.5 * sprite_grid.grid / vec2<f32>(textureDimensions(sprite_atlas));
+    let uv = clamp(in.local * 0.5 + 0.5, margin, 1.0 - margin);
+
+    // Sampled for every ball to keep control flow uniform
+    let sprite = textureSample(sprite_atlas, sprite_sampler, (cell + uv) / sprite_grid.grid);
+    if (in.sprite == NO_SPRITE) {
+        return shade(in.color, in);
+    }
+    return shade(mix(in.color, sprite.rgb, sprite.a), in);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return vec4<f32>(in.color, 1.0);
-}
\ No newline at end of file
+}
+
+// Balls come out with premultiplied alpha, which also covers the SDF edge
+@fragment
+fn fs_ball(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(ball_color(in) * in.alpha, in.alpha);
+}
+
+@fragment
+fn fs_sdf(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Signed distance to the edge, negative inside
+    let dist = length(in.local) - 1.0;
+    // Size of a pixel in local units
+    let px = fwidth(dist);
+
+    let coverage = clamp(0.5 - dist / px, 0.0, 1.0);
+
+    var color = ball_color(in);
+    if (circle_style.outline_width > 0.0) {
+        let outline_dist = dist + circle_style.outline_width * px;
+        let outline = clamp(0.5 + outline_dist / px, 0.0, 1.0);
+        color = mix(color, srgb_to_linear(circle_style.outline_color.rgb), outline * circle_style.outline_color.a);
+    }
+
+    let alpha = coverage * in.alpha;
+    return vec4<f32>(color * alpha, alpha);
+}
@@ -0,0 +1,139 @@
+use crate::util::Vec2;
+
+/// Anything that can be stored in a [`QuadTree`](crate::quadtree::QuadTree).
+pub trait BoundingShape: Clone {
+    fn aabb(&self) -> Aabb;
+
+    /// Exact overlap test against an area. Defaults to the bounding box test.
+    fn intersects_aabb(&self, area: &Aabb) -> bool {
+        self.aabb().intersects(area)
+    }
+
+    /// Exact overlap test against another shape of the same kind. Defaults to the bounding box test.
+    fn intersects(&self, other: &Self) -> bool {
+        self.aabb().intersects(&other.aabb())
+    }
+
+    fn contains_point(&self, point: Vec2) -> bool {
+        self.aabb().contains_point(point)
+    }
+}
+
+/// The point on the segment from `start` to `end` closest to `point`.
+pub fn closest_point_on_segment(point: Vec2, start: Vec2, end: Vec2) -> Vec2 {
+    let segment = end - start;
+    let length_sq = segment.dot(&segment);
+    if length_sq == 0.0 { return start }
+
+    let t = ((point - start).dot(&segment) / length_sq).clamp(0.0, 1.0);
+    start + segment * t
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub pos: Vec2,
+    pub size: Vec2,
+}
+
+impl Aabb {
+    pub const fn new(pos: Vec2, size: Vec2) -> Self {
+        Self { pos, size }
+    }
+
+    pub fn from_min_max(min: Vec2, max: Vec2) -> Self {
+        Self { pos: min, size: max - min }
+    }
+
+    pub fn max(&self) -> Vec2 {
+        self.pos + self.size
+    }
+
+    pub fn center(&self) -> Vec2 {
+        self.pos + self.size / 2.0
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Aabb::from_min_max(self.pos.min(other.pos), self.max().max(other.max()))
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        let translated = other.pos - self.pos;
+
+        // don't ask
+        translated.both_less_eq(&self.size) && (-translated).both_less_eq(&other.size)
+    }
+
+    /// Whether `other` lies completely inside this box.
+    pub fn contains(&self, other: &Aabb) -> bool {
+        other.pos.both_greater_eq(&self.pos) && other.max().both_less_eq(&self.max())
+    }
+
+    pub fn contains_point(&self, point: Vec2) -> bool {
+        point.both_greater_eq(&self.pos) && point.both_less_eq(&self.max())
+    }
+
+    pub fn inflate(&self, amount: f32) -> Self {
+        Aabb::new(self.pos - Vec2::fill(amount), self.size + Vec2::fill(amount * 2.0))
+    }
+
+    /// Distance along the ray at which it enters this box, or `None` if it misses
+    /// within `max_dist`. A ray starting inside enters at `0.0`.
+    pub fn ray_entry(&self, origin: Vec2, dir: Vec2, max_dist: f32) -> Option<f32> {
+        let mut t_min = 0f32;
+        let mut t_max = max_dist;
+
+        for (o, d, lo, hi) in [
+            (origin.x, dir.x, self.pos.x, self.pos.x + self.size.x),
+            (origin.y, dir.y, self.pos.y, self.pos.y + self.size.y),
+        ] {
+            if d == 0.0 {
+                if o < lo || o > hi { return None }
+                continue;
+            }
+
+            let t1 = (lo - o) / d;
+            let t2 = (hi - o) / d;
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+        }
+
+        (t_min <= t_max).then_some(t_min)
+    }
+}
+
+impl BoundingShape for Aabb {
+    fn aabb(&self) -> Aabb {
+        *self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub center: Vec2,
+    pub radius: f32,
+}
+
+impl Circle {
+    pub const fn new(center: Vec2, radius: f32) -> Self {
+        Self { center, radius }
+    }
+}
+
+impl BoundingShape for Circle {
+    fn aabb(&self) -> Aabb {
+        Aabb::new(self.center - Vec2::fill(self.radius), Vec2::fill(self.radius * 2.0))
+    }
+
+    fn intersects_aabb(&self, area: &Aabb) -> bool {
+        let closest = self.center.max(area.pos).min(area.max());
+        closest.distance(&self.center) <= self.radius
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        self.center.distance(&other.center) <= self.radius + other.radius
+    }
+
+    fn contains_point(&self, point: Vec2) -> bool {
+        self.center.distance(&point) <= self.radius
+    }
+}
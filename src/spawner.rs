@@ -0,0 +1,58 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{physics::Ball, util::{Vec2, Color}};
+
+/// Drops balls into the world on its own, the same way every run for a given seed.
+#[derive(Debug, Clone)]
+pub struct Spawner {
+    pub(crate) position: Vec2,
+    /// Balls spawned per frame.
+    pub(crate) rate: u32,
+    pub(crate) radius: f32,
+    /// Balls appear up to this far left or right of `position`.
+    pub(crate) spread: f32,
+    pub(crate) velocity: Vec2,
+    /// Stops after this many balls.
+    pub(crate) max_balls: usize,
+    /// Spawns ghosts that fade out over this many steps instead of balls, see
+    /// [`Ghost`](crate::ghost::Ghost).
+    pub(crate) ghost_lifetime: Option<u32>,
+    spawned: usize,
+    rng: StdRng,
+}
+
+impl Spawner {
+    pub fn new(position: Vec2, seed: u64) -> Self {
+        Self {
+            position,
+            rate: 1,
+            radius: 10.0,
+            spread: 0.0,
+            velocity: Vec2::fill(0.0),
+            max_balls: usize::MAX,
+            ghost_lifetime: None,
+            spawned: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.spawned >= self.max_balls
+    }
+
+    /// The balls to add this frame.
+    pub fn spawn(&mut self) -> Vec<(Ball, Color)> {
+        let count = (self.rate as usize).min(self.max_balls - self.spawned);
+        self.spawned += count;
+
+        (0..count)
+            .map(|_| {
+                let offset = if self.spread > 0.0 { self.rng.gen_range(-self.spread..=self.spread) } else { 0.0 };
+                let mut ball = Ball::new(self.position.x + offset, self.position.y, self.radius);
+                ball.vel = self.velocity;
+
+                (ball, Color::random_from(&mut self.rng))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,179 @@
+use std::{io, num::NonZeroU32, path::Path};
+
+use wgpu::util::DeviceExt;
+
+use crate::{uniform::SpriteAtlasUniform, image::Image};
+
+/// Sprites packed into one texture, in a grid of square cells.
+pub struct SpriteAtlas {
+    pub(crate) columns: u32,
+    pub(crate) rows: u32,
+    /// Number of sprites, cells past this are empty.
+    pub(crate) count: u32,
+    /// RGBA, 8 bits per channel, top row first.
+    pub(crate) rgba: Vec<u8>,
+}
+
+impl SpriteAtlas {
+    /// Side of one cell in pixels. Sprites are stretched to fill it.
+    pub const CELL_SIZE: u32 = 128;
+
+    /// Loads `path` if it's a PNG, or every PNG inside it in name order if it's a directory.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut paths = match path.is_dir() {
+            true => std::fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("png")))
+                .collect(),
+            false => vec![path.to_path_buf()],
+        };
+        paths.sort();
+
+        if paths.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No PNGs in {}", path.display())));
+        }
+
+        let sprites = paths.iter().map(|path| Image::load_png(path)).collect::<io::Result<Vec<_>>>()?;
+        Ok(Self::from_sprites(&sprites))
+    }
+
+    /// Packs `sprites` into a roughly square grid.
+    pub fn from_sprites(sprites: &[Image]) -> Self {
+        let count = sprites.len() as u32;
+        let columns = ((count as f32).sqrt().ceil() as u32).max(1);
+        let rows = count.div_ceil(columns).max(1);
+
+        let (width, height) = (columns * Self::CELL_SIZE, rows * Self::CELL_SIZE);
+        let mut rgba = vec![0; (width * height * 4) as usize];
+
+        for (i, sprite) in sprites.iter().enumerate() {
+            let (cell_x, cell_y) = (i as u32 % columns * Self::CELL_SIZE, i as u32 / columns * Self::CELL_SIZE);
+            for y in 0..Self::CELL_SIZE {
+                for x in 0..Self::CELL_SIZE {
+                    let pixel = sprite.sample((x as f32 + 0.5) / Self::CELL_SIZE as f32, (y as f32 + 0.5) / Self::CELL_SIZE as f32);
+                    let start = (((cell_y + y) * width + cell_x + x) * 4) as usize;
+                    rgba[start..start + 4].copy_from_slice(&pixel);
+                }
+            }
+        }
+
+        Self { columns, rows, count, rgba }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.columns * Self::CELL_SIZE, self.rows * Self::CELL_SIZE)
+    }
+}
+
+/// The atlas on the GPU, bound at group 1 of the ball pipelines. Starts out empty.
+pub struct SpriteBindings {
+    pub(crate) bind_group_layout: wgpu::BindGroupLayout,
+    pub(crate) bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+}
+
+impl SpriteBindings {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sprite Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let empty = SpriteAtlas { columns: 1, rows: 1, count: 0, rgba: vec![0; 4] };
+        let bind_group = create_bind_group(device, queue, &bind_group_layout, &sampler, &empty, (1, 1));
+
+        Self { bind_group_layout, bind_group, sampler }
+    }
+
+    pub fn set_atlas(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, atlas: &SpriteAtlas) {
+        self.bind_group = create_bind_group(device, queue, &self.bind_group_layout, &self.sampler, atlas, atlas.size());
+    }
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    atlas: &SpriteAtlas,
+    (width, height): (u32, u32),
+) -> wgpu::BindGroup {
+    let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Sprite Atlas"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+    queue.write_texture(
+        texture.as_image_copy(),
+        &atlas.rgba,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(width * 4),
+            rows_per_image: NonZeroU32::new(height),
+        },
+        size,
+    );
+
+    let grid = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Sprite Grid Buffer"),
+        contents: bytemuck::cast_slice(&[SpriteAtlasUniform::new(atlas.columns, atlas.rows)]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Sprite Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.create_view(&wgpu::TextureViewDescriptor::default())),
+            },
+            wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(sampler) },
+            wgpu::BindGroupEntry { binding: 2, resource: grid.as_entire_binding() },
+        ],
+    })
+}
@@ -0,0 +1,107 @@
+use wgpu::include_wgsl;
+
+use crate::trails::create_fullscreen_pipeline;
+
+/// Stands in for a surface without an sRGB format. Frames are drawn into an sRGB texture
+/// like on any other target, then encoded into the surface by a fullscreen pass.
+pub struct SrgbOutput {
+    view: wgpu::TextureView,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    size: (u32, u32),
+}
+
+impl SrgbOutput {
+    /// What everything before the final pass draws in.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// `output_format` is the surface's, which doesn't encode sRGB itself.
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat, (width, height): (u32, u32)) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("fullscreen.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sRGB Output Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sRGB Output Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = create_fullscreen_pipeline(device, &layout, &shader, (output_format, 1), "sRGB Output Pipeline", "fs_encode_srgb", wgpu::BlendState::REPLACE);
+
+        let (view, bind_group) = create_texture(device, &bind_group_layout, width, height);
+
+        Self { view, bind_group_layout, bind_group, pipeline, size: (width, height) }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if self.size == (width, height) { return }
+
+        (self.view, self.bind_group) = create_texture(device, &self.bind_group_layout, width, height);
+        self.size = (width, height);
+    }
+
+    /// Where to draw the frame instead of the surface.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Copies the frame drawn into [`SrgbOutput::view`] into `output`, sRGB encoded.
+    pub fn encode(&self, device: &wgpu::Device, queue: &wgpu::Queue, output: &wgpu::TextureView) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("sRGB Output Encoder"),
+        });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("sRGB Output Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+fn create_texture(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, width: u32, height: u32) -> (wgpu::TextureView, wgpu::BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("sRGB Output Texture"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: SrgbOutput::FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("sRGB Output Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }],
+    });
+
+    (view, bind_group)
+}
@@ -1,49 +1,116 @@
-use std::time::Instant;
+use std::{path::Path, time::{Instant, SystemTime, UNIX_EPOCH}};
 
-use cgmath::{Vector2, Quaternion, Vector3};
-use winit::{window::Window, event_loop::ControlFlow};
+use cgmath::Vector2;
+use rand::{rngs::StdRng, SeedableRng};
+use winit::{window::Window, event_loop::ControlFlow, event::VirtualKeyCode};
 
-use crate::{render_state::RenderState, input_handler::InputHandler, physics::{Physics, Ball}, instance::Instance, util::Color};
+use crate::{render_state::{RenderState, CircleMode}, input_handler::InputHandler, physics::{Physics, Ball, BallHandle}, instance::{Instance, InstanceRaw}, util::{Color, Vec2}, broad_phase::AutoTuner, spawner::Spawner, recorder::Recorder, timestep::{FixedTimestep, Smoothing}, debug_draw::{DebugDraw, DebugOverlays}, hud::{Hud, HudStats}, coloring::Coloring, post::PostEffect, sprite::SpriteAtlas, pacing::{FrameLimiter, present_mode_name}, scenery::{Scenery, SceneryStyle}, ghost::Ghost};
 
 pub struct State {
     pub(crate) render_state: RenderState,
     pub(crate) input_handler: InputHandler,
     pub(crate) physics: Physics,
-    update_times: Vec<f32>,
+    pub(crate) spawners: Vec<Spawner>,
+    /// Balls are despawned after this many steps and fade out, or live forever if `None`.
+    pub(crate) ball_lifetime: Option<u32>,
+    /// Opacity of every ball, below `1.0` to show where they crowd together.
+    pub(crate) ball_opacity: f32,
+    ghosts: Vec<Ghost>,
+    /// Sort balls along a Z-order curve every this many frames, or never if `0`.
+    pub(crate) reorder_interval: u32,
+    pub(crate) timestep: FixedTimestep,
+    /// How balls are drawn between steps when running in real time.
+    pub(crate) smoothing: Smoothing,
+    /// Steps every frame regardless of real time, for running as fast as possible and only
+    /// drawing every so often. `None` keeps to `timestep`.
+    pub(crate) steps_per_frame: Option<u32>,
+    pub(crate) frame_limiter: FrameLimiter,
+    /// While recording, every frame advances exactly one step so the output plays back smoothly.
+    pub(crate) recorder: Option<Recorder>,
+    pub(crate) debug_overlays: DebugOverlays,
+    /// How the boundary and obstacles are drawn.
+    pub(crate) scenery_style: SceneryStyle,
+    pub(crate) hud: Hud,
+    pub(crate) coloring: Coloring,
+    /// Color each ball was spawned with, in the same order as `physics.balls`.
+    spawn_colors: Vec<Color>,
+    /// Sprites in the atlas, which balls take turns using. `None` draws plain balls.
+    sprite_count: Option<u32>,
+    /// Colors indexed by ball handle that replace the ones balls are spawned with.
+    pub(crate) replay_colors: Option<Vec<Color>>,
+    /// Colors balls added with the mouse, seeded so runs can be repeated.
+    rng: StdRng,
+    frame: u64,
 }
 
 impl State {
+    /// Frames trails take to fade when turned on with a key.
+    const TRAIL_LENGTH: u32 = 30;
+    const SEED: u64 = 0x5eed;
+    /// Equal balls touching one in the middle of a tightly packed pile.
+    const MAX_CONTACTS: f32 = 6.0;
+    /// Steps a despawned ball takes to fade out.
+    const FADE_STEPS: u32 = 30;
+    /// Opacity ghosts from spawners start with.
+    const GHOST_ALPHA: f32 = 0.5;
+    /// Ball opacities to cycle through with a key.
+    const OPACITIES: [f32; 3] = [1.0, 0.5, 0.25];
+
     pub async fn new(window: &Window) -> Self {
+        Self::with_render_state(RenderState::new(window).await)
+    }
+
+    /// A state that renders offscreen, see [`RenderState::new_headless`].
+    pub async fn new_headless(width: u32, height: u32) -> Option<Self> {
+        Some(Self::with_render_state(RenderState::new_headless(width, height).await?))
+    }
+
+    fn with_render_state(mut render_state: RenderState) -> Self {
+        let physics = Physics::default();
+
+        if let Some(bounds) = physics.boundary.aabb() {
+            render_state.camera.fit(&bounds);
+        }
+
         Self {
-            render_state: RenderState::new(window).await,
+            render_state,
             input_handler: InputHandler::new(),
-            physics: Physics::default(),
-            update_times: Vec::new(),
+            physics,
+            spawners: Vec::new(),
+            ball_lifetime: None,
+            ball_opacity: 1.0,
+            ghosts: Vec::new(),
+            reorder_interval: 120,
+            timestep: FixedTimestep::new(60),
+            smoothing: Smoothing::default(),
+            steps_per_frame: None,
+            frame_limiter: FrameLimiter::default(),
+            recorder: None,
+            debug_overlays: DebugOverlays::default(),
+            scenery_style: SceneryStyle::default(),
+            hud: Hud::default(),
+            coloring: Coloring::default(),
+            spawn_colors: Vec::new(),
+            sprite_count: None,
+            replay_colors: None,
+            rng: StdRng::seed_from_u64(Self::SEED),
+            frame: 0,
         }
     }
 
     pub fn update(&mut self, control_flow: &mut ControlFlow) {
-        let start = Instant::now();
-        self.input_handler.handle_input();
-        self.add_input_balls();
-        self.physics.update();
-        self.sync_balls();
-
-        self.update_times.push((Instant::now() - start).as_secs_f32());
-        let len = self.update_times.len();
-        if len >= 30 {
-            let mut avg = 0f32;
-            for time in self.update_times.iter() {
-                avg += time;
-            }
-            avg /= len as f32;
-            println!("Average update time: {}ms", (avg * 1000000f32).round() / 1000f32);
+        let now = Instant::now();
+        self.frame_limiter.frame_started(now);
+        let (steps, alpha) = match (self.steps_per_frame, &self.recorder) {
+            // The recorder was told how many steps each frame covers when it started
+            (_, Some(recorder)) => (recorder.steps_per_frame, None),
+            (Some(steps), None) => (steps, None),
+            (None, None) => (self.timestep.advance(now), Some(self.timestep.alpha())),
+        };
+        self.advance(steps, alpha);
 
-            self.update_times.clear();
-        }
-
-        match self.render_state.render() {
-            Ok(_) => {}
+        match self.render() {
+            Ok(_) => self.record_frame(),
             // Reconfigure the surface if lost
             Err(wgpu::SurfaceError::Lost) => self.render_state.resize(self.render_state.size),
             // The system is out of memory, we should probably quit
@@ -53,28 +120,382 @@ impl State {
         }
     }
 
+    /// Draws the current state along with the HUD.
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let stats = HudStats {
+            balls: self.physics.balls.len(),
+            contacts: self.physics.contact_count(),
+            broad_phase: &self.physics.broad_phase_stats,
+            substeps: self.physics.substeps,
+            iterations: self.physics.iterations,
+            color_mode: self.coloring.mode.name(),
+            colormap: self.coloring.colormap.name(),
+            color_range: self.coloring.current_range(),
+        };
+        self.render_state.set_hud(&self.hud.build(&stats));
+
+        let start = Instant::now();
+        let result = self.render_state.render();
+        self.hud.record_render(Instant::now() - start);
+
+        result
+    }
+
+    /// Advances everything by one frame without drawing, which is one step unless
+    /// `steps_per_frame` says otherwise.
+    pub fn step(&mut self) {
+        self.advance(self.steps_per_frame.unwrap_or(1), None);
+    }
+
+    /// One step of the simulation alone, which gives the same result every run for the same
+    /// spawners and input.
+    pub fn simulate(&mut self) {
+        self.add_spawned_balls();
+        self.physics.update();
+        let substeps = self.physics.substeps;
+        self.ghosts.retain_mut(|ghost| ghost.update(substeps));
+        self.despawn_expired();
+        self.frame += 1;
+        self.reorder_balls();
+    }
+
+    /// Removes every ball and starts counting frames from zero, so the simulation can be
+    /// run again from the start.
+    pub fn reset(&mut self) {
+        self.physics.clear();
+        self.render_state.clear_instances();
+        self.spawn_colors.clear();
+        self.ghosts.clear();
+        self.rng = StdRng::seed_from_u64(Self::SEED);
+        self.frame = 0;
+        self.timestep.reset();
+    }
+
+    /// Handles input, then runs `steps` simulation steps. Balls are drawn `alpha` of the way
+    /// into the next step, or right where they are if `None`.
+    fn advance(&mut self, steps: u32, alpha: Option<f32>) {
+        self.hud.frame(Instant::now());
+        self.input_handler.handle_input();
+        self.handle_keys();
+        self.move_camera();
+        self.add_input_balls();
+
+        let start = Instant::now();
+        for _ in 0..steps {
+            self.simulate();
+        }
+        self.hud.record_physics(Instant::now() - start);
+
+        self.sync_balls(alpha);
+        self.draw_scenery();
+        self.draw_debug_overlays();
+    }
+
+    pub fn handle_keys(&mut self) {
+        for key in std::mem::take(&mut self.input_handler.keys_pressed) {
+            match key {
+                VirtualKeyCode::T => {
+                    self.physics.auto_tuner = match self.physics.auto_tuner {
+                        Some(_) => None,
+                        None => Some(AutoTuner::default()),
+                    };
+                    println!("Quadtree auto-tuning: {}", self.physics.auto_tuner.is_some());
+                }
+                VirtualKeyCode::M => {
+                    self.render_state.circle_mode = match self.render_state.circle_mode {
+                        CircleMode::Mesh => CircleMode::Sdf,
+                        CircleMode::Sdf => CircleMode::Mesh,
+                    };
+                }
+                VirtualKeyCode::O => {
+                    let width = if self.render_state.outline_width() > 0.0 { 0.0 } else { 1.5 };
+                    self.render_state.set_outline(width, [0.0, 0.0, 0.0, 1.0]);
+                }
+                VirtualKeyCode::R => {
+                    if self.recorder.is_some() {
+                        self.stop_recording();
+                    } else {
+                        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+                        self.start_recording(format!("recordings/{}", secs), 1);
+                    }
+                }
+                VirtualKeyCode::C => {
+                    self.coloring.set_mode(self.coloring.mode.next());
+                    println!("Color mode: {}", self.coloring.mode.name());
+                }
+                VirtualKeyCode::P => {
+                    self.coloring.colormap = self.coloring.colormap.next();
+                    println!("Colormap: {}", self.coloring.colormap.name());
+                }
+                VirtualKeyCode::L => {
+                    self.coloring.toggle_locked_range();
+                    println!("Color range: {:?}", self.coloring.range);
+                }
+                VirtualKeyCode::G => {
+                    let length = match self.render_state.trail_length() {
+                        Some(_) => None,
+                        None => Some(Self::TRAIL_LENGTH),
+                    };
+                    self.render_state.set_trails(length);
+                    println!("Trails: {}", length.is_some());
+                }
+                VirtualKeyCode::B => {
+                    let effects = match self.render_state.post_effects().is_empty() {
+                        true => PostEffect::neon(),
+                        false => Vec::new(),
+                    };
+                    println!("Neon post-processing: {}", !effects.is_empty());
+                    self.render_state.set_post_effects(effects);
+                }
+                VirtualKeyCode::N => {
+                    let supported = self.render_state.supported_sample_counts();
+                    let i = supported.iter().position(|&count| count == self.render_state.sample_count()).unwrap_or(0);
+                    let next = supported[(i + 1) % supported.len()];
+                    println!("MSAA: {}x", self.render_state.set_sample_count(next));
+                }
+                VirtualKeyCode::V => {
+                    let supported = self.render_state.supported_present_modes();
+                    let Some(i) = supported.iter().position(|&mode| Some(mode) == self.render_state.present_mode()) else { continue };
+                    let next = supported[(i + 1) % supported.len()];
+                    if let Some(mode) = self.render_state.set_present_mode(next) {
+                        println!("Present mode: {}", present_mode_name(mode));
+                    }
+                }
+                VirtualKeyCode::A => {
+                    let i = Self::OPACITIES.iter().position(|&opacity| opacity == self.ball_opacity).unwrap_or(0);
+                    self.ball_opacity = Self::OPACITIES[(i + 1) % Self::OPACITIES.len()];
+                    println!("Ball opacity: {}", self.ball_opacity);
+                }
+                VirtualKeyCode::X => self.despawn_all(),
+                VirtualKeyCode::Z => {
+                    self.render_state.set_layer_order(!self.render_state.layer_order());
+                    println!("Layer ordering: {}", self.render_state.layer_order());
+                }
+                VirtualKeyCode::I => {
+                    self.smoothing = self.smoothing.next();
+                    println!("Smoothing between steps: {}", self.smoothing.name());
+                }
+                VirtualKeyCode::S => {
+                    let shading = self.render_state.shading().next();
+                    self.render_state.set_shading(shading);
+                    println!("Shading: {}", shading.name());
+                }
+                VirtualKeyCode::H => self.hud.visible = !self.hud.visible,
+                VirtualKeyCode::F1 => toggle(&mut self.debug_overlays.quadtree, "Quadtree overlay"),
+                VirtualKeyCode::F2 => toggle(&mut self.debug_overlays.velocities, "Velocity overlay"),
+                VirtualKeyCode::F3 => {
+                    toggle(&mut self.debug_overlays.contacts, "Contact overlay");
+                    self.physics.contacts = self.debug_overlays.contacts.then(Vec::new);
+                }
+                VirtualKeyCode::F4 => toggle(&mut self.debug_overlays.aabbs, "AABB overlay"),
+                VirtualKeyCode::F5 => toggle(&mut self.debug_overlays.boundary, "Boundary overlay"),
+                _ => {}
+            }
+        }
+    }
+
+    /// Starts saving rendered frames, see [`Recorder::new`] for formats.
+    pub fn start_recording(&mut self, path: impl AsRef<Path>, every: u32) {
+        match Recorder::new(&path, every, self.timestep.steps_per_second(), self.steps_per_frame.unwrap_or(1)) {
+            Ok(recorder) => {
+                println!("Recording to {}", path.as_ref().display());
+                self.recorder = Some(recorder);
+            }
+            Err(e) => eprintln!("Could not record to {}: {}", path.as_ref().display(), e),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        let Some(recorder) = self.recorder.take() else { return };
+        let saved = recorder.saved();
+        match recorder.finish() {
+            Ok(_) => println!("Recording stopped, saved {} frames", saved),
+            Err(e) => eprintln!("Could not finish recording: {}", e),
+        }
+
+        // Don't try to catch up on the time spent recording
+        self.timestep.reset();
+    }
+
+    /// Hands the frame just rendered to the recorder, if recording.
+    pub fn record_frame(&mut self) {
+        let Some(recorder) = &mut self.recorder else { return };
+        let render_state = &mut self.render_state;
+
+        if let Err(e) = recorder.record(|| render_state.capture()) {
+            eprintln!("Recording failed: {}", e);
+            self.stop_recording();
+        }
+    }
+
+    pub fn move_camera(&mut self) {
+        let camera = &mut self.render_state.camera;
+
+        let pan_delta = std::mem::replace(&mut self.input_handler.pan_delta, Vec2::fill(0.0));
+        camera.pan_by_screen(pan_delta);
+
+        let scroll = std::mem::take(&mut self.input_handler.scroll);
+        if scroll != 0.0 {
+            let anchor = self.input_handler.mouse_pos().unwrap_or(camera.viewport / 2.0);
+            camera.zoom_at(anchor, 1.1f32.powf(scroll));
+        }
+    }
+
     pub fn add_input_balls(&mut self) {
         let balls_to_add = self.input_handler.balls_to_add.clone();
         self.input_handler.balls_to_add.clear();
 
-        for pos in balls_to_add.iter() {
-            self.physics.add_ball(Ball::new(pos.x, pos.y, 10.0));
+        for screen_pos in balls_to_add.iter() {
+            let pos = self.render_state.camera.screen_to_world(*screen_pos);
+            let color = Color::random_from(&mut self.rng);
+            self.add_ball(Ball::new(pos.x, pos.y, 10.0), color);
+        }
+    }
 
-            let instance = Instance {
-                position: Vector2::new(pos.x, pos.y),
-                rotation: Quaternion::from_sv(1.0, Vector3::unit_y()),
-                scale: 10.0,
-                color: Color::random(),
-            };
+    pub fn add_spawned_balls(&mut self) {
+        let spawned = self.spawners.iter_mut()
+            .flat_map(|spawner| {
+                let ghost_lifetime = spawner.ghost_lifetime;
+                spawner.spawn().into_iter().map(move |(ball, color)| (ball, color, ghost_lifetime))
+            })
+            .collect::<Vec<_>>();
 
-            self.render_state.add_instance(instance);
+        for (ball, color, ghost_lifetime) in spawned.into_iter() {
+            match ghost_lifetime {
+                Some(lifetime) => self.ghosts.push(Ghost::new(ball, color, Self::GHOST_ALPHA, lifetime)),
+                None => { self.add_ball(ball, color); }
+            }
         }
     }
 
-    pub fn sync_balls(&mut self) {
+    /// Adds a ball to the simulation along with its instance. `color` is overridden by
+    /// `replay_colors` if that has one for the ball.
+    pub fn add_ball(&mut self, ball: Ball, color: Color) -> BallHandle {
+        let mut instance = Instance {
+            position: Vector2::new(ball.pos.x, ball.pos.y),
+            rotation: ball.angle,
+            scale: ball.radius,
+            color,
+            alpha: self.ball_opacity,
+            layer: 0.0,
+            sprite: None,
+            occlusion: 0.0,
+        };
+
+        let handle = self.physics.add_ball(ball);
+        let replay_color = self.replay_colors.as_ref().and_then(|colors| colors.get(handle.0 as usize));
+        instance.color = replay_color.copied().unwrap_or(color);
+        instance.sprite = self.sprite_for(handle);
+
+        self.render_state.add_instance(instance);
+        self.spawn_colors.push(instance.color);
+        handle
+    }
+
+    /// Removes a ball from the simulation, leaving a ghost of it to fade out where it was.
+    pub fn despawn(&mut self, handle: BallHandle) {
+        let Some(index) = self.physics.index_of(handle) else { return };
+        let Some(ball) = self.physics.remove_ball(handle) else { return };
+        let instance = self.render_state.instances[index];
+        self.render_state.swap_remove_instance(index);
+        self.spawn_colors.swap_remove(index);
+
+        let mut ghost = Ghost::new(ball, instance.color, instance.alpha, Self::FADE_STEPS);
+        ghost.sprite = instance.sprite;
+        self.ghosts.push(ghost);
+    }
+
+    pub fn despawn_all(&mut self) {
+        for handle in self.physics.handles.clone() {
+            self.despawn(handle);
+        }
+    }
+
+    /// Despawns balls that have outlived `ball_lifetime`.
+    fn despawn_expired(&mut self) {
+        let Some(lifetime) = self.ball_lifetime else { return };
+        let expired = self.physics.balls.iter().zip(self.physics.handles.iter())
+            .filter(|(ball, _)| ball.age >= lifetime)
+            .map(|(_, &handle)| handle)
+            .collect::<Vec<_>>();
+
+        for handle in expired {
+            self.despawn(handle);
+        }
+    }
+
+    /// Draws every ball, including ones already added, with one of the sprites in `atlas`.
+    pub fn set_sprites(&mut self, atlas: &SpriteAtlas) {
+        self.render_state.set_sprite_atlas(atlas);
+        self.sprite_count = (atlas.count > 0).then_some(atlas.count);
+
+        for (i, &handle) in self.physics.handles.iter().enumerate() {
+            let instance = Instance { sprite: self.sprite_for(handle), ..self.render_state.instances[i] };
+            self.render_state.update_instance(i, instance);
+        }
+    }
+
+    fn sprite_for(&self, handle: BallHandle) -> Option<u16> {
+        // Any more couldn't be told apart from no sprite
+        self.sprite_count.map(|count| (handle.0 % count.min(InstanceRaw::NO_SPRITE as u32)) as u16)
+    }
+
+    /// Rebuilds the boundary and obstacles from `physics`, so they're drawn wherever balls
+    /// actually collide with them.
+    pub fn draw_scenery(&mut self) {
+        let mut scenery = Scenery::default();
+        scenery.physics(&self.physics, &self.scenery_style, 1.0 / self.render_state.camera.zoom);
+        self.render_state.set_scenery(&scenery.vertices);
+    }
+
+    pub fn draw_debug_overlays(&mut self) {
+        let mut debug_draw = DebugDraw::default();
+        if self.debug_overlays.any() {
+            debug_draw.physics(&self.physics, &self.debug_overlays);
+        }
+        self.render_state.set_debug_lines(&debug_draw.vertices);
+    }
+
+    pub fn reorder_balls(&mut self) {
+        if self.reorder_interval == 0 || !self.frame.is_multiple_of(self.reorder_interval as u64) { return }
+
+        let order = self.physics.sort_spatially();
+        if !order.is_empty() {
+            self.render_state.reorder_instances(&order);
+            self.spawn_colors = order.iter().map(|&i| self.spawn_colors[i]).collect();
+        }
+    }
+
+    /// Copies the balls and ghosts into their instances, placed with `smoothing` when given
+    /// an `alpha`. Only balls that moved or changed look are uploaded again, so a pile that
+    /// has come to rest costs next to nothing.
+    pub fn sync_balls(&mut self, alpha: Option<f32>) {
+        let mut colors = self.spawn_colors.clone();
+        self.coloring.apply(&self.physics, &self.spawn_colors, &mut colors);
+
+        let (smoothing, alpha) = match alpha {
+            Some(alpha) => (self.smoothing, alpha),
+            None => (Smoothing::Off, 0.0),
+        };
+
         for (i, ball) in self.physics.balls.iter().enumerate() {
-            self.render_state.instances[i].position = ball.pos.into();
+            let instance = Instance {
+                position: smoothing.position(ball.prev_pos, ball.pos, alpha).into(),
+                rotation: smoothing.angle(ball.prev_angle, ball.angle, alpha),
+                color: colors[i],
+                alpha: self.ball_opacity,
+                occlusion: (ball.contacts as f32 / Self::MAX_CONTACTS).min(1.0),
+                ..self.render_state.instances[i]
+            };
+            self.render_state.update_instance(i, instance);
         }
-        self.render_state.recreate_instance_buffer();
+
+        let ghosts = self.ghosts.iter().map(|ghost| ghost.instance(smoothing, alpha)).collect::<Vec<_>>();
+        self.render_state.set_ghosts(&ghosts);
     }
-}
\ No newline at end of file
+}
+
+fn toggle(flag: &mut bool, name: &str) {
+    *flag = !*flag;
+    println!("{}: {}", name, *flag);
+}
@@ -0,0 +1,164 @@
+use std::{f32::consts::{PI, TAU}, time::{Duration, Instant}};
+
+use crate::util::Vec2;
+
+/// Turns elapsed real time into a whole number of fixed simulation steps.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    pub(crate) step: Duration,
+    /// Steps run at most per frame, so a slow frame doesn't make the next one even slower.
+    pub(crate) max_steps: u32,
+    accumulator: Duration,
+    last: Option<Instant>,
+}
+
+impl FixedTimestep {
+    pub fn new(steps_per_second: u32) -> Self {
+        Self {
+            step: Duration::from_secs(1) / steps_per_second,
+            max_steps: 4,
+            accumulator: Duration::ZERO,
+            last: None,
+        }
+    }
+
+    pub fn steps_per_second(&self) -> u32 {
+        (1.0 / self.step.as_secs_f64()).round() as u32
+    }
+
+    /// How many steps to run to catch up with `now`.
+    pub fn advance(&mut self, now: Instant) -> u32 {
+        let elapsed = self.last.map_or(self.step, |last| now - last);
+        self.last = Some(now);
+        self.accumulator += elapsed;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        if steps > self.max_steps {
+            self.accumulator = Duration::ZERO;
+            steps = self.max_steps;
+        }
+
+        steps
+    }
+
+    /// How far real time has got into the next step, from `0.0` to `1.0`.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f32() / self.step.as_secs_f32()).min(1.0)
+    }
+
+    /// Forgets elapsed time, e.g. after stepping by hand for a while.
+    pub fn reset(&mut self) {
+        self.accumulator = Duration::ZERO;
+        self.last = None;
+    }
+}
+
+/// Where things are drawn between fixed steps, so motion stays smooth when the display
+/// doesn't refresh in time with the simulation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Smoothing {
+    /// Exactly where the last step left them.
+    Off,
+    /// Between the last two steps, which is smooth but a step behind.
+    #[default]
+    Interpolate,
+    /// Carried on past the last step at the same speed, which is up to date but overshoots
+    /// when things collide.
+    Extrapolate,
+}
+
+impl Smoothing {
+    pub fn name(self) -> &'static str {
+        match self {
+            Smoothing::Off => "off",
+            Smoothing::Interpolate => "interpolate",
+            Smoothing::Extrapolate => "extrapolate",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Smoothing::Off => Smoothing::Interpolate,
+            Smoothing::Interpolate => Smoothing::Extrapolate,
+            Smoothing::Extrapolate => Smoothing::Off,
+        }
+    }
+
+    /// Where to draw something that moved from `previous` to `current` in the last step,
+    /// `alpha` of the way into the next one.
+    pub fn position(self, previous: Vec2, current: Vec2, alpha: f32) -> Vec2 {
+        match self {
+            Smoothing::Off => current,
+            Smoothing::Interpolate => previous + (current - previous) * alpha,
+            Smoothing::Extrapolate => current + (current - previous) * alpha,
+        }
+    }
+
+    /// Like [`Smoothing::position`] for angles in radians, turning the short way round.
+    pub fn angle(self, previous: f32, current: f32, alpha: f32) -> f32 {
+        let turned = (current - previous + PI).rem_euclid(TAU) - PI;
+        match self {
+            Smoothing::Off => current,
+            Smoothing::Interpolate => current - turned * (1.0 - alpha),
+            Smoothing::Extrapolate => current + turned * alpha,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether two angles point the same way.
+    fn same_angle(a: f32, b: f32) -> bool {
+        ((a - b + PI).rem_euclid(TAU) - PI).abs() < 1e-5
+    }
+
+    #[test]
+    fn position_follows_the_last_step() {
+        let (previous, current) = (Vec2::new(0.0, 0.0), Vec2::new(2.0, -4.0));
+        assert_eq!(Smoothing::Off.position(previous, current, 0.5), current);
+        assert_eq!(Smoothing::Interpolate.position(previous, current, 0.0), previous);
+        assert_eq!(Smoothing::Interpolate.position(previous, current, 0.5), Vec2::new(1.0, -2.0));
+        assert_eq!(Smoothing::Interpolate.position(previous, current, 1.0), current);
+        assert_eq!(Smoothing::Extrapolate.position(previous, current, 0.0), current);
+        assert_eq!(Smoothing::Extrapolate.position(previous, current, 0.5), Vec2::new(3.0, -6.0));
+    }
+
+    #[test]
+    fn angle_without_wrapping() {
+        assert_eq!(Smoothing::Off.angle(1.0, 2.0, 0.5), 2.0);
+        assert!(same_angle(Smoothing::Interpolate.angle(1.0, 2.0, 0.25), 1.25));
+        assert!(same_angle(Smoothing::Extrapolate.angle(1.0, 2.0, 0.5), 2.5));
+    }
+
+    #[test]
+    fn angle_turns_the_short_way_across_zero() {
+        // Spun forwards past a full turn, which `Ball::update_pos` wraps back to near zero
+        let (previous, current) = (TAU - 0.1, 0.1);
+        assert!(same_angle(Smoothing::Interpolate.angle(previous, current, 0.0), previous));
+        assert!(same_angle(Smoothing::Interpolate.angle(previous, current, 0.5), 0.0));
+        assert!(same_angle(Smoothing::Interpolate.angle(previous, current, 1.0), current));
+        assert!(same_angle(Smoothing::Extrapolate.angle(previous, current, 0.5), 0.2));
+
+        // Spun backwards, where `%` leaves the angle negative
+        let (previous, current) = (0.1, -0.1);
+        assert!(same_angle(Smoothing::Interpolate.angle(previous, current, 0.5), 0.0));
+        assert!(same_angle(Smoothing::Extrapolate.angle(previous, current, 1.0), -0.3));
+        let (previous, current) = (-TAU + 0.1, -0.1);
+        assert!(same_angle(Smoothing::Interpolate.angle(previous, current, 0.5), 0.0));
+    }
+
+    #[test]
+    fn angle_turns_at_most_half_a_turn() {
+        let turned = Smoothing::Extrapolate.angle(0.0, PI - 0.01, 1.0);
+        assert!(same_angle(turned, 2.0 * (PI - 0.01)));
+        let turned = Smoothing::Extrapolate.angle(0.0, PI + 0.01, 1.0);
+        assert!(same_angle(turned, 2.0 * (-PI + 0.01)));
+    }
+}
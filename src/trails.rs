@@ -0,0 +1,203 @@
+use std::cell::Cell;
+
+use wgpu::include_wgsl;
+
+use crate::{render_target::{color_attachment, multisampled_view}, post::PostProcessor};
+
+/// Balls are drawn into a texture that keeps its contents between frames and fades a
+/// little each frame, leaving trails behind moving balls. It's premultiplied, so trails
+/// fade into whatever they're drawn over.
+pub struct Trails {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    /// Where the balls are actually drawn with MSAA on. It keeps its samples between frames
+    /// and gets resolved into `view` at the end of every pass.
+    multisampled: Option<wgpu::TextureView>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    fade_pipeline: wgpu::RenderPipeline,
+    blit_pipeline: wgpu::RenderPipeline,
+    /// Clamps at zero after fading, which would otherwise go negative.
+    clamp_pipeline: wgpu::RenderPipeline,
+    size: (u32, u32),
+    sample_count: u32,
+    /// Frames a trail takes to fade out completely.
+    pub(crate) length: u32,
+    /// Set when the texture is new, so its first frame starts out empty.
+    needs_clear: Cell<bool>,
+}
+
+impl Trails {
+    /// Float whatever the target is, so small fade steps aren't rounded away like they
+    /// would be in 8 bits.
+    pub const FORMAT: wgpu::TextureFormat = PostProcessor::HDR_FORMAT;
+    /// Longest trail that still fades all the way at half float precision.
+    pub const MAX_LENGTH: u32 = 1000;
+
+    /// `output` is the format and sample count of the pass the trails are copied into, and
+    /// `sample_count` what balls are drawn into the trails with.
+    pub fn new(device: &wgpu::Device, output: (wgpu::TextureFormat, u32), (width, height): (u32, u32), length: u32, sample_count: u32) -> Self {
+        let shader = device.create_shader_module(include_wgsl!("fullscreen.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Trail Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+
+        let fade_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Trail Fade Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let blit_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Trail Blit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // dst - constant, so every trail is transparent after `length` frames
+        let fade = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::Constant,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::ReverseSubtract,
+        };
+        let fade_blend = wgpu::BlendState { color: fade, alpha: fade };
+
+        let fade_pipeline = create_fullscreen_pipeline(device, &fade_layout, &shader, (Self::FORMAT, sample_count), "Trail Fade Pipeline", "fs_fade", fade_blend);
+        let blit_pipeline = create_fullscreen_pipeline(device, &blit_layout, &shader, output, "Trail Blit Pipeline", "fs_blit", wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+
+        let max = wgpu::BlendComponent { src_factor: wgpu::BlendFactor::One, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Max };
+        let clamp_blend = wgpu::BlendState { color: max, alpha: max };
+        let clamp_pipeline = create_fullscreen_pipeline(device, &fade_layout, &shader, (Self::FORMAT, sample_count), "Trail Clamp Pipeline", "fs_zero", clamp_blend);
+
+        let (texture, view, bind_group) = create_texture(device, &bind_group_layout, width, height);
+        let multisampled = multisampled_view(device, Self::FORMAT, (width, height), sample_count);
+
+        Self {
+            texture,
+            view,
+            multisampled,
+            bind_group_layout,
+            bind_group,
+            fade_pipeline,
+            blit_pipeline,
+            clamp_pipeline,
+            size: (width, height),
+            sample_count,
+            length: length.clamp(1, Self::MAX_LENGTH),
+            needs_clear: Cell::new(true),
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if self.size == (width, height) { return }
+
+        (self.texture, self.view, self.bind_group) = create_texture(device, &self.bind_group_layout, width, height);
+        self.multisampled = multisampled_view(device, Self::FORMAT, (width, height), self.sample_count);
+        self.size = (width, height);
+        self.needs_clear.set(true);
+    }
+
+    /// Starts a pass into the trail texture with the previous frame faded, to draw this
+    /// frame's balls into.
+    pub fn begin_pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder) -> wgpu::RenderPass<'a> {
+        let load = match self.needs_clear.replace(false) {
+            true => wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            false => wgpu::LoadOp::Load,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Trail Pass"),
+            color_attachments: &[Some(color_attachment(self.multisampled.as_ref(), &self.view, load))],
+            depth_stencil_attachment: None,
+        });
+
+        let fade = 1.0 / self.length as f64;
+        render_pass.set_pipeline(&self.fade_pipeline);
+        render_pass.set_blend_constant(wgpu::Color { r: fade, g: fade, b: fade, a: fade });
+        render_pass.draw(0..3, 0..1);
+
+        render_pass.set_pipeline(&self.clamp_pipeline);
+        render_pass.draw(0..3, 0..1);
+
+        render_pass
+    }
+
+    /// Draws the trails over a pass drawing to a target of the same size.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.blit_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView, wgpu::BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Trail Texture"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: Trails::FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Trail Bind Group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }],
+    });
+
+    (texture, view, bind_group)
+}
+
+pub fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    (format, sample_count): (wgpu::TextureFormat, u32),
+    label: &str,
+    fs_entry: &str,
+    blend: wgpu::BlendState,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_fullscreen",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fs_entry,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(blend),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+        multiview: None,
+    })
+}
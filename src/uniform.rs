@@ -1,13 +1,104 @@
-use winit::dpi::PhysicalSize;
+use bytemuck::Zeroable;
+
+use crate::{util::Vec2, lighting::Shading};
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct VpSizeUniform {
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    pub(crate) center: [f32; 2],
     pub(crate) viewport_size: [f32; 2],
+    pub(crate) zoom: f32,
+    pub(crate) rotation: f32,
+    _padding: [f32; 2],
+}
+
+impl CameraUniform {
+    pub fn new(center: Vec2, zoom: f32, rotation: f32, viewport_size: Vec2) -> Self {
+        Self {
+            center: [center.x, center.y],
+            viewport_size: [viewport_size.x, viewport_size.y],
+            zoom,
+            rotation,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// How SDF circles are outlined. A width of `0.0` disables the outline.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CircleStyleUniform {
+    pub(crate) outline_color: [f32; 4],
+    /// In pixels.
+    pub(crate) outline_width: f32,
+    _padding: [f32; 3],
+}
+
+impl CircleStyleUniform {
+    pub fn new(outline_width: f32, outline_color: [f32; 4]) -> Self {
+        Self { outline_color, outline_width, _padding: [0.0; 3] }
+    }
+}
+
+impl Default for CircleStyleUniform {
+    fn default() -> Self {
+        Self::new(0.0, [0.0, 0.0, 0.0, 1.0])
+    }
+}
+
+/// Layout of the [`SpriteAtlas`](crate::sprite::SpriteAtlas) texture.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpriteAtlasUniform {
+    /// Columns and rows of cells.
+    pub(crate) grid: [f32; 2],
+    _padding: [f32; 2],
+}
+
+impl SpriteAtlasUniform {
+    pub fn new(columns: u32, rows: u32) -> Self {
+        Self { grid: [columns as f32, rows as f32], _padding: [0.0; 2] }
+    }
 }
 
-impl VpSizeUniform {
-    pub fn new(size: PhysicalSize<u32>) -> Self {
-        Self { viewport_size: size.into() }
+/// [`Shading`] as the shader sees it.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightingUniform {
+    /// Normalized.
+    pub(crate) direction: [f32; 3],
+    /// `0` for flat shading.
+    pub(crate) enabled: u32,
+    pub(crate) ambient: f32,
+    pub(crate) diffuse: f32,
+    pub(crate) specular: f32,
+    pub(crate) shininess: f32,
+    pub(crate) rim: f32,
+    pub(crate) rim_power: f32,
+    pub(crate) occlusion: f32,
+    _padding: f32,
+}
+
+impl From<&Shading> for LightingUniform {
+    fn from(shading: &Shading) -> Self {
+        let lighting = match shading {
+            Shading::Flat => return Self::zeroed(),
+            Shading::Lit(lighting) => lighting,
+        };
+
+        let [x, y, z] = lighting.direction;
+        let length = (x * x + y * y + z * z).sqrt().max(f32::EPSILON);
+        Self {
+            direction: [x / length, y / length, z / length],
+            enabled: 1,
+            ambient: lighting.ambient,
+            diffuse: lighting.diffuse,
+            specular: lighting.specular,
+            shininess: lighting.shininess,
+            rim: lighting.rim,
+            rim_power: lighting.rim_power,
+            occlusion: lighting.occlusion,
+            _padding: 0.0,
+        }
     }
-}
\ No newline at end of file
+}
@@ -20,7 +20,7 @@ impl RenderCircle {
 
     pub fn get_vertices(&mut self) -> Vec<Vertex> {
         match &self.vertices {
-            Some(e) => return e.clone(),
+            Some(e) => e.clone(),
             None => {
                 let mut vertices = Vec::new();
                 let angle_divisor = self.num_vertices as f32;
@@ -34,14 +34,14 @@ impl RenderCircle {
                 }
         
                 self.vertices = Some(vertices.clone());
-                return vertices;
+                vertices
             }
         }
     }
 
     pub fn get_indices(&mut self) -> Vec<u32> {
         match &self.indices {
-            Some(e) => return e.clone(),
+            Some(e) => e.clone(),
             None => {
                 let mut indices = Vec::new();
 
@@ -52,12 +52,27 @@ impl RenderCircle {
                 }
 
                 self.indices = Some(indices.clone());
-                return indices;
+                indices
             }
         }
     }
 }
 
+/// Unit quad covering a circle of radius 1, drawn with a signed distance function.
+pub struct RenderQuad;
+
+impl RenderQuad {
+    pub fn get_vertices(color: [f32; 3]) -> Vec<Vertex> {
+        [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]].into_iter()
+            .map(|position| Vertex { position, color })
+            .collect()
+    }
+
+    pub fn get_indices() -> Vec<u32> {
+        vec![0, 1, 2, 0, 2, 3]
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vec2 {
     pub x: f32,
@@ -77,15 +92,15 @@ impl Vec2 {
         let a = self.x - other.x;
         let b = self.y - other.y;
 
-        return ((a*a) + (b*b)).sqrt()
+        ((a*a) + (b*b)).sqrt()
     }
 
     pub fn length(&self) -> f32 {
-        return ((self.x * self.x) + (self.y * self.y)).sqrt()
+        ((self.x * self.x) + (self.y * self.y)).sqrt()
     }
 
     pub fn normalize(self) -> Self {
-        return self / Vec2::fill(self.length())
+        self / Vec2::fill(self.length())
     }
 
     pub fn both_greater_eq(&self, other: &Vec2) -> bool {
@@ -95,11 +110,28 @@ impl Vec2 {
     pub fn both_less_eq(&self, other: &Vec2) -> bool {
         self.x <= other.x && self.y <= other.y
     }
+
+    pub fn min(self, other: Vec2) -> Self {
+        Vec2::new(self.x.min(other.x), self.y.min(other.y))
+    }
+
+    pub fn max(self, other: Vec2) -> Self {
+        Vec2::new(self.x.max(other.x), self.y.max(other.y))
+    }
+
+    pub fn dot(&self, other: &Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// This vector rotated by 90 degrees.
+    pub fn perp(self) -> Self {
+        Vec2::new(-self.y, self.x)
+    }
 }
 
-impl Into<Vector2<f32>> for Vec2 {
-    fn into(self) -> Vector2<f32> {
-        Vector2 { x: self.x, y: self.y }
+impl From<Vec2> for Vector2<f32> {
+    fn from(v: Vec2) -> Self {
+        Vector2 { x: v.x, y: v.y }
     }
 }
 
@@ -195,7 +227,10 @@ impl Neg for Vec2 {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// A color as sRGB components from `0.0` to `1.0`, the space hex codes, images and
+/// colormaps are written in. Shading and blending happen in linear light, see
+/// [`Color::to_linear`].
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     r: f32,
     g: f32,
@@ -211,18 +246,198 @@ impl Color {
         Self { r, g, b }
     }
 
+    /// From `0xRRGGBB`.
+    pub const fn from_hex(hex: u32) -> Self {
+        Self::new(
+            ((hex >> 16) & 0xff) as f32 / 255.0,
+            ((hex >> 8) & 0xff) as f32 / 255.0,
+            (hex & 0xff) as f32 / 255.0,
+        )
+    }
+
+    /// Reads `RRGGBB`, with or without a leading `#`.
+    pub fn parse_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim();
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        // `from_str_radix` would also take a sign
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) { return None }
+        u32::from_str_radix(hex, 16).ok().map(Self::from_hex)
+    }
+
+    pub fn from_linear(linear: LinearColor) -> Self {
+        Self::new(linear_to_srgb(linear.r), linear_to_srgb(linear.g), linear_to_srgb(linear.b))
+    }
+
+    pub fn to_linear(self) -> LinearColor {
+        LinearColor::new(srgb_to_linear(self.r), srgb_to_linear(self.g), srgb_to_linear(self.b))
+    }
+
     pub fn random() -> Self {
-        let mut rng = rand::thread_rng();
+        Color::random_from(&mut rand::thread_rng())
+    }
+
+    pub fn random_from(rng: &mut impl Rng) -> Self {
         Color::new(rng.gen(), rng.gen(), rng.gen())
     }
 
     pub fn into_arr(self) -> [f32; 3] {
         [self.r, self.g, self.b]
     }
+
+    /// Packs into sRGB bytes with red in the lowest byte and `alpha` in the highest, for
+    /// the shader to decode.
+    pub fn to_rgba8(self, alpha: f32) -> u32 {
+        let to_byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u32;
+        to_byte(self.r) | (to_byte(self.g) << 8) | (to_byte(self.b) << 16) | (to_byte(alpha) << 24)
+    }
 }
 
-impl Into<[f32; 3]> for Color {
-    fn into(self) -> [f32; 3] {
-        [self.r, self.g, self.b]
+impl From<Color> for [f32; 3] {
+    fn from(c: Color) -> Self {
+        [c.r, c.g, c.b]
+    }
+}
+
+/// A color in linear light, where components are proportional to the amount of light, so
+/// adding and scaling them behaves like mixing light.
+#[derive(Debug, Clone, Copy)]
+pub struct LinearColor {
+    pub(crate) r: f32,
+    pub(crate) g: f32,
+    pub(crate) b: f32,
+}
+
+impl LinearColor {
+    pub const fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    pub fn to_srgb(self) -> Color {
+        Color::from_linear(self)
     }
-}
\ No newline at end of file
+}
+
+impl Mul<f32> for LinearColor {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        LinearColor::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl From<LinearColor> for [f32; 3] {
+    fn from(c: LinearColor) -> Self {
+        [c.r, c.g, c.b]
+    }
+}
+
+/// Decodes one sRGB component, the same as `srgb_to_linear` in the shaders.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Bits of the half float nearest to `value`. Values too small for a normal half float
+/// become zero and values too large become infinity.
+pub fn to_f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = (bits >> 16) & 0x8000;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x007f_ffff;
+
+    if value.is_nan() { return (sign | 0x7e00) as u16 }
+    if exponent >= 0x1f { return (sign | 0x7c00) as u16 }
+    if exponent <= 0 { return sign as u16 }
+
+    // Rounding up can carry into the exponent, which is still the nearest value
+    let half = sign | ((exponent as u32) << 10) | (mantissa >> 13);
+    (half + ((mantissa >> 12) & 1)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(to_f16_bits(0.0), 0x0000);
+        assert_eq!(to_f16_bits(-0.0), 0x8000);
+        assert_eq!(to_f16_bits(1.0), 0x3c00);
+        assert_eq!(to_f16_bits(-2.0), 0xc000);
+        assert_eq!(to_f16_bits(0.5), 0x3800);
+        assert_eq!(to_f16_bits(65504.0), 0x7bff);
+        assert_eq!(to_f16_bits(1e6), 0x7c00);
+        assert_eq!(to_f16_bits(1e-8), 0x0000);
+        // Nearest to pi is 3.140625
+        assert_eq!(to_f16_bits(PI), 0x4248);
+        assert_eq!(to_f16_bits(f32::NAN) & 0x7e00, 0x7e00);
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn srgb_known_values() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!(close(srgb_to_linear(1.0), 1.0));
+        assert!(close(srgb_to_linear(0.5), 0.214041));
+        assert!(close(srgb_to_linear(0.02), 0.02 / 12.92));
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!(close(linear_to_srgb(1.0), 1.0));
+        assert!(close(linear_to_srgb(0.18), 0.461356));
+        assert!(close(linear_to_srgb(0.001), 0.01292));
+    }
+
+    #[test]
+    fn srgb_pieces_meet() {
+        assert!(close(srgb_to_linear(0.04045 - 1e-6), srgb_to_linear(0.04045 + 1e-6)));
+        assert!(close(linear_to_srgb(0.0031308 - 1e-7), linear_to_srgb(0.0031308 + 1e-7)));
+    }
+
+    #[test]
+    fn srgb_increases() {
+        let values = (0..=1000).map(|i| i as f32 / 1000.0);
+        let linear = values.clone().map(srgb_to_linear).collect::<Vec<_>>();
+        let srgb = values.map(linear_to_srgb).collect::<Vec<_>>();
+        assert!(linear.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(srgb.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn srgb_round_trips_every_byte() {
+        for byte in 0..=255u32 {
+            let c = byte as f32 / 255.0;
+            assert!(close(linear_to_srgb(srgb_to_linear(c)), c), "{}", byte);
+
+            let color = Color::new(c, c, c);
+            assert_eq!(color.to_linear().to_srgb().to_rgba8(0.0), byte | (byte << 8) | (byte << 16));
+        }
+    }
+
+    #[test]
+    fn hex_and_bytes() {
+        let color = Color::from_hex(0x336699);
+        assert_eq!(color.into_arr(), [0.2, 0.4, 0.6]);
+        assert_eq!(color.to_rgba8(1.0), 0xff996633);
+        assert_eq!(Color::new(-1.0, 2.0, 0.5).to_rgba8(0.5), 0x8080ff00);
+    }
+
+    #[test]
+    fn parse_hex() {
+        let color = Color::from_hex(0x15171d).into_arr();
+        for hex in ["15171d", "#15171d", "#15171D", " #15171d\n"] {
+            assert_eq!(Color::parse_hex(hex).map(Color::into_arr), Some(color), "{:?}", hex);
+        }
+    }
+
+    #[test]
+    fn parse_malformed_hex() {
+        for hex in ["", "#", "15171", "#15171d0", "15 71d", "##15171d", "+15171", "-15171", "0x1517", "15171g", "é15171"] {
+            assert!(Color::parse_hex(hex).is_none(), "{:?}", hex);
+        }
+    }
+}
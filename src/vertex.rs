@@ -4,6 +4,7 @@ use bytemuck::{Pod, Zeroable};
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct Vertex {
     pub(crate) position: [f32; 2],
+    /// sRGB.
     pub(crate) color: [f32; 3],
 }
 